@@ -0,0 +1,86 @@
+// Mixed allocate/free workload over a 64 MiB pool, validating the heap as it goes.
+//
+//   cargo run --example cpu_pool -- --seed 7 --ops 200000
+//
+// Exits with status 1 as soon as `validate` reports a broken invariant, so it doubles as a smoke test.
+use std::process::ExitCode;
+
+use speed_allocator::SpeedAllocator;
+
+const POOL_SIZE: usize = 64 << 20;
+const WINDOWS: usize = 8;
+
+fn main() -> ExitCode {
+  let (mut seed, ops) = match parse_args() {
+    Ok(args) => args,
+    Err(message) => {
+      eprintln!("{message}\nusage: cpu_pool [--seed N] [--ops N]");
+      return ExitCode::FAILURE;
+    }
+  };
+  let mut allocator = SpeedAllocator::new(POOL_SIZE);
+  let mut live = Vec::new();
+  let mut failures = 0;
+  for op in 0..ops {
+    let roll = xorshift(&mut seed);
+    if live.is_empty() || !roll.is_multiple_of(3) {
+      // mostly small requests with the occasional large buffer
+      let size = if roll.is_multiple_of(64) {
+        (roll >> 8) as usize % (1 << 20)
+      } else {
+        (roll >> 8) as usize % 4096
+      };
+      let alignment = 1 << ((roll >> 40) % 8);
+      match allocator.allocate(size, alignment) {
+        Some(ptr) => live.push(ptr),
+        None => failures += 1,
+      }
+    } else {
+      let victim = (roll >> 16) as usize % live.len();
+      allocator.deallocate(live.swap_remove(victim));
+    }
+    if op.is_multiple_of(1000) {
+      if let Err(error) = allocator.validate() {
+        eprintln!("validation failed after {op} operations: {error}");
+        return ExitCode::FAILURE;
+      }
+    }
+  }
+  if let Err(error) = allocator.validate() {
+    eprintln!("validation failed after the workload: {error}");
+    return ExitCode::FAILURE;
+  }
+
+  println!("{ops} operations, {failures} failed allocations, {} live", live.len());
+  println!("{:#?}", allocator.stats());
+  println!("fragmentation per {} KiB window:", (POOL_SIZE / WINDOWS) >> 10);
+  for window in 0..WINDOWS {
+    let lo = window * POOL_SIZE / WINDOWS;
+    println!(
+      "  {window}: {:.3}",
+      allocator.fragmentation_in(lo, lo + POOL_SIZE / WINDOWS)
+    );
+  }
+  ExitCode::SUCCESS
+}
+
+fn parse_args() -> Result<(u64, usize), String> {
+  let (mut seed, mut ops) = (0x9e37_79b9_7f4a_7c15, 100_000);
+  let mut args = std::env::args().skip(1);
+  while let Some(flag) = args.next() {
+    let value = args.next().ok_or_else(|| format!("{flag} needs a value"))?;
+    match flag.as_str() {
+      "--seed" => seed = value.parse().map_err(|err| format!("--seed {value}: {err}"))?,
+      "--ops" => ops = value.parse().map_err(|err| format!("--ops {value}: {err}"))?,
+      _ => return Err(format!("unknown argument {flag}")),
+    }
+  }
+  Ok((seed.max(1), ops))
+}
+
+fn xorshift(state: &mut u64) -> u64 {
+  *state ^= *state << 13;
+  *state ^= *state >> 7;
+  *state ^= *state << 17;
+  *state
+}
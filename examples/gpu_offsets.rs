@@ -0,0 +1,175 @@
+// Offset-only suballocation of several device heaps: the host pointers are never touched, every allocation is
+// turned into an offset into its heap, as a GPU buffer suballocator would hand out. Allocations are tagged with
+// the frame that made them and retired two frames later, then each heap is compacted through a frozen view and
+// its final layout is printed as JSON.
+//
+//   cargo run --example gpu_offsets --features tagging -- --seed 7 --ops 200000
+//
+// Exits with status 1 as soon as `validate` reports a broken invariant, so it doubles as a smoke test.
+use std::fmt::Write;
+use std::process::ExitCode;
+use std::ptr::NonNull;
+
+use speed_allocator::{AlignmentOrigin, SpeedAllocator};
+
+const HEAPS: usize = 4;
+const HEAP_SIZE: usize = 16 << 20;
+const FRAME_OPS: usize = 1000;
+// frames an allocation survives when nothing frees it earlier
+const FRAME_LIFETIME: u64 = 2;
+
+struct Resource {
+  heap: usize,
+  ptr: NonNull<u8>,
+  frame: u64,
+}
+
+fn main() -> ExitCode {
+  let (mut seed, ops) = match parse_args() {
+    Ok(args) => args,
+    Err(message) => {
+      eprintln!("{message}\nusage: gpu_offsets [--seed N] [--ops N]");
+      return ExitCode::FAILURE;
+    }
+  };
+  let mut heaps: Vec<SpeedAllocator> = (0..HEAPS)
+    .map(|_| {
+      let mut heap = SpeedAllocator::new(HEAP_SIZE);
+      heap.set_alignment_origin(AlignmentOrigin::PoolRelative);
+      heap
+    })
+    .collect();
+  let mut live: Vec<Resource> = Vec::new();
+  let (mut failures, mut retired) = (0, 0);
+  for op in 0..ops {
+    let frame = (op / FRAME_OPS) as u64;
+    if op.is_multiple_of(FRAME_OPS) && frame >= FRAME_LIFETIME {
+      let tag = frame - FRAME_LIFETIME;
+      retired += heaps.iter_mut().map(|heap| heap.dealloc_all_tagged(tag)).sum::<usize>();
+      live.retain(|resource| resource.frame != tag);
+    }
+
+    let roll = xorshift(&mut seed);
+    if live.is_empty() || !roll.is_multiple_of(4) {
+      let heap = (roll >> 4) as usize % HEAPS;
+      // vertex and constant buffers, with the occasional texture
+      let size = if roll.is_multiple_of(32) {
+        64 << 10 << ((roll >> 8) % 4)
+      } else {
+        256 + (roll >> 8) as usize % (16 << 10)
+      };
+      let alignment = 256 << ((roll >> 40) % 4);
+      let Some(ptr) = heaps[heap].allocate_tagged(size, alignment, frame) else {
+        failures += 1;
+        continue;
+      };
+      let offset = offset_of(&heaps[heap], ptr);
+      if !offset.is_multiple_of(alignment) {
+        eprintln!("heap {heap}: offset {offset} is not aligned to {alignment}");
+        return ExitCode::FAILURE;
+      }
+      live.push(Resource { heap, ptr, frame });
+    } else {
+      let Resource { heap, ptr, .. } = live.swap_remove((roll >> 16) as usize % live.len());
+      heaps[heap].deallocate(ptr);
+    }
+
+    if op % FRAME_OPS == FRAME_OPS - 1 {
+      if let Some((heap, error)) = first_invalid(&heaps) {
+        eprintln!("heap {heap}: validation failed after {op} operations: {error}");
+        return ExitCode::FAILURE;
+      }
+    }
+  }
+
+  let mut moved = 0;
+  for (index, heap) in heaps.iter_mut().enumerate() {
+    let plan = heap.freeze().plan_compaction();
+    if let Err(error) = heap.validate_plan(plan.relocations()) {
+      eprintln!("heap {index}: compaction plan rejected: {error}");
+      return ExitCode::FAILURE;
+    }
+    moved += heap.commit_plan(&plan).iter().filter(|step| step.is_ok()).count();
+  }
+  if let Some((heap, error)) = first_invalid(&heaps) {
+    eprintln!("heap {heap}: validation failed after compaction: {error}");
+    return ExitCode::FAILURE;
+  }
+
+  eprintln!("{ops} operations, {failures} failed allocations, {retired} bytes retired by frame, {moved} moved");
+  println!("{}", to_json(&heaps));
+  ExitCode::SUCCESS
+}
+
+// Offset of the allocation at `ptr` from the start of its heap.
+fn offset_of(heap: &SpeedAllocator, ptr: NonNull<u8>) -> usize {
+  let block = heap
+    .get_block_info(ptr)
+    .expect("allocate_tagged returns the start of a live allocation");
+  block.offset + block.alignment_waste
+}
+
+fn first_invalid(heaps: &[SpeedAllocator]) -> Option<(usize, speed_allocator::ValidationError)> {
+  heaps
+    .iter()
+    .enumerate()
+    .find_map(|(index, heap)| heap.validate().err().map(|error| (index, error)))
+}
+
+fn to_json(heaps: &[SpeedAllocator]) -> String {
+  let mut out = String::from("{\"heaps\":[");
+  for (index, heap) in heaps.iter().enumerate() {
+    let stats = heap.stats();
+    if index > 0 {
+      out.push(',');
+    }
+    let _ = write!(
+      out,
+      "{{\"pool_size\":{},\"used_bytes\":{},\"free_bytes\":{},\"largest_free_block\":{},\"live_allocations\":{},\
+       \"external_fragmentation\":{:.4},\"blocks\":[",
+      stats.pool_size,
+      stats.used_bytes,
+      stats.free_bytes,
+      stats.largest_free_block,
+      stats.live_allocations,
+      stats.external_fragmentation,
+    );
+    for (i, block) in heap.blocks().enumerate() {
+      if i > 0 {
+        out.push(',');
+      }
+      let _ = write!(
+        out,
+        "{{\"offset\":{},\"size\":{},\"free\":{},\"tag\":{}}}",
+        block.offset + block.alignment_waste,
+        block.size - block.alignment_waste,
+        block.is_free,
+        block.tag
+      );
+    }
+    out.push_str("]}");
+  }
+  out.push_str("]}");
+  out
+}
+
+fn parse_args() -> Result<(u64, usize), String> {
+  let (mut seed, mut ops) = (0x9e37_79b9_7f4a_7c15, 100_000);
+  let mut args = std::env::args().skip(1);
+  while let Some(flag) = args.next() {
+    let value = args.next().ok_or_else(|| format!("{flag} needs a value"))?;
+    match flag.as_str() {
+      "--seed" => seed = value.parse().map_err(|err| format!("--seed {value}: {err}"))?,
+      "--ops" => ops = value.parse().map_err(|err| format!("--ops {value}: {err}"))?,
+      _ => return Err(format!("unknown argument {flag}")),
+    }
+  }
+  Ok((seed.max(1), ops))
+}
+
+fn xorshift(state: &mut u64) -> u64 {
+  *state ^= *state << 13;
+  *state ^= *state >> 7;
+  *state ^= *state << 17;
+  *state
+}
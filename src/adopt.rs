@@ -0,0 +1,179 @@
+use std::fmt;
+
+use crate::allocator::SpeedAllocator;
+use crate::block::Block;
+use crate::facade::Allocation;
+use crate::mapping::MIN_ALLOC_SIZE;
+
+/// A live allocation of another allocator, handed to [`SpeedAllocator::adopt_pool`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LiveRange {
+  pub offset: usize,
+  pub size: usize,
+  #[cfg_attr(
+    feature = "tagging",
+    doc = "Tag to attach, as with [`SpeedAllocator::allocate_tagged`]."
+  )]
+  #[cfg_attr(
+    not(feature = "tagging"),
+    doc = "Tag to attach; needs the `tagging` feature, adopting fails without it."
+  )]
+  pub tag: Option<u64>,
+}
+
+/// Why [`SpeedAllocator::adopt_pool`] refused a layout, naming the offending range by its index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AdoptError {
+  /// The allocator still has live allocations.
+  NotEmpty,
+  /// The range is empty.
+  EmptyRange { index: usize },
+  /// The range starts before the previous one.
+  Unsorted { index: usize },
+  /// The range shares an 8-byte granule with the previous one, so they can't sit in separate blocks.
+  Overlap { index: usize },
+  /// The range runs past the end of the pool.
+  OutOfPool { index: usize },
+  /// The range carries a tag but the `tagging` feature is off.
+  TagsUnsupported { index: usize },
+}
+
+impl fmt::Display for AdoptError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AdoptError::NotEmpty => write!(f, "allocator still has live allocations"),
+      AdoptError::EmptyRange { index } => write!(f, "range {index} is empty"),
+      AdoptError::Unsorted { index } => write!(f, "range {index} starts before the previous one"),
+      AdoptError::Overlap { index } => write!(f, "range {index} overlaps the previous one"),
+      AdoptError::OutOfPool { index } => write!(f, "range {index} runs past the pool"),
+      AdoptError::TagsUnsupported { index } => write!(f, "range {index} has a tag but tagging is off"),
+    }
+  }
+}
+
+impl std::error::Error for AdoptError {}
+
+impl SpeedAllocator {
+  /// Takes over the allocations of another allocator that managed the same pool: `live` lists them by offset,
+  /// and each becomes a used block while every gap between them becomes one free block. Block boundaries sit
+  /// on 8-byte granules, so a range is widened to the granules it touches, its start kept as the alignment
+  /// adjustment; ranges smaller than 8 bytes get a block of their own all the same. Returns one handle per range,
+  /// in input order.
+  ///
+  /// The allocator must have no live allocations. Like [`reset`](Self::reset), adopting starts a new
+  /// [`epoch`](Self::epoch).
+  pub fn adopt_pool(&mut self, live: &[LiveRange]) -> Result<Vec<Allocation>, AdoptError> {
+    if !self.allocations.is_empty() {
+      return Err(AdoptError::NotEmpty);
+    }
+    let pool_size = self.pool.len();
+    let mut blocks = Vec::with_capacity(live.len() * 2 + 1);
+    let mut end = 0;
+    for (index, range) in live.iter().enumerate() {
+      if range.size == 0 {
+        return Err(AdoptError::EmptyRange { index });
+      }
+      if index > 0 && range.offset < live[index - 1].offset {
+        return Err(AdoptError::Unsorted { index });
+      }
+      let start = range.offset - range.offset % MIN_ALLOC_SIZE;
+      if start < end {
+        return Err(AdoptError::Overlap { index });
+      }
+      let block_end = range
+        .offset
+        .checked_add(range.size)
+        .and_then(|range_end| range_end.checked_next_multiple_of(MIN_ALLOC_SIZE))
+        .filter(|&block_end| block_end <= pool_size)
+        .ok_or(AdoptError::OutOfPool { index })?;
+      if range.tag.is_some() && cfg!(not(feature = "tagging")) {
+        return Err(AdoptError::TagsUnsupported { index });
+      }
+      if start > end {
+        blocks.push(Block::new(end, start - end));
+      }
+      blocks.push(Block {
+        is_free: false,
+        adjustment: range.offset - start,
+        #[cfg(feature = "tagging")]
+        tag: range.tag,
+        ..Block::new(start, block_end - start)
+      });
+      end = block_end;
+    }
+    if end < pool_size {
+      blocks.push(Block::new(end, pool_size - end));
+    }
+    self.rebuild_layout(blocks);
+    self.poisoned.set(false);
+    self.epoch += 1;
+    Ok(
+      live
+        .iter()
+        .map(|range| {
+          self
+            .allocation_handle(self.pool.ptr_at(range.offset))
+            .expect("adopted range is indexed")
+        })
+        .collect(),
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn range(offset: usize, size: usize) -> LiveRange {
+    LiveRange { offset, size, tag: None }
+  }
+
+  #[test]
+  fn adopted_layout_validates_and_frees_back_to_one_block() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let live = [
+      range(0, 3),
+      range(16, 100),
+      range(120, 4),
+      range(203, 8),
+      range(4000, 96),
+    ];
+    let handles = allocator.adopt_pool(&live).unwrap();
+    allocator.validate().unwrap();
+    assert_eq!(allocator.stats().live_allocations, live.len());
+    for (handle, range) in handles.iter().zip(&live) {
+      let block = allocator.resolve_handle(handle).unwrap();
+      assert_eq!(block.offset + block.alignment_waste, range.offset);
+      assert!(handle.size >= range.size);
+    }
+    // the gap between the third and fourth range is one free block
+    assert_eq!(allocator.free_run_at(128), 72);
+
+    for handle in handles {
+      allocator.deallocate(handle.ptr);
+    }
+    let blocks: Vec<_> = allocator
+      .blocks()
+      .map(|block| (block.offset, block.size, block.is_free))
+      .collect();
+    assert_eq!(blocks, [(0, 4096, true)]);
+    allocator.validate().unwrap();
+  }
+
+  #[test]
+  fn bad_layouts_are_refused() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let cases = [
+      (vec![range(0, 8), range(64, 0)], AdoptError::EmptyRange { index: 1 }),
+      (vec![range(64, 8), range(0, 8)], AdoptError::Unsorted { index: 1 }),
+      (vec![range(0, 3), range(4, 8)], AdoptError::Overlap { index: 1 }),
+      (vec![range(4090, 8)], AdoptError::OutOfPool { index: 0 }),
+    ];
+    for (live, error) in cases {
+      assert_eq!(allocator.adopt_pool(&live), Err(error));
+    }
+    allocator.validate().unwrap();
+    allocator.allocate(64, 8).unwrap();
+    assert_eq!(allocator.adopt_pool(&[]), Err(AdoptError::NotEmpty));
+  }
+}
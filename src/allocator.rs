@@ -1,36 +1,2593 @@
-use crate::block::BlockHeader;
+use std::alloc::Layout;
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::cmp::Reverse;
+#[cfg(feature = "tagging")]
+use std::collections::BTreeSet;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::mem::size_of;
+use std::ptr::NonNull;
+#[cfg(feature = "backtrace")]
+use std::sync::Arc;
+use std::time::Duration;
 
-pub struct TLSF {
-  pool: Vec<u8>,
-  free_lists: Vec<Vec<BlockHeader>>,
+use crate::bitmap::Bitmap;
+use crate::block::{Block, BlockId, Blocks};
+use crate::budget::OperationBudget;
+#[cfg(feature = "perf-counters")]
+use crate::demand::DemandTracker;
+use crate::error::AllocError;
+use crate::facade::{next_allocator_id, Allocation};
+use crate::frozen;
+use crate::iter::{BlockInfo, PhysicalBlockIter};
+use crate::mapping::{binmap_down, binmap_up, BinRef, BIN_COUNT, BLOCK_COUNT, LINEAR, MIN_ALLOC_SIZE, SUB_BIN};
+use crate::pool::Pool;
+#[cfg(feature = "profiling")]
+use crate::profile::SizeWindow;
+#[cfg(feature = "time-series")]
+use crate::timeseries::UtilizationSeries;
+use crate::validate::{ValidationCursor, ValidationError};
+
+/// Usage snapshot returned by [`SpeedAllocator::stats`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+  pub pool_size: usize,
+  pub used_bytes: usize,
+  pub free_bytes: usize,
+  pub free_block_count: usize,
+  pub largest_free_block: usize,
+  /// `1.0 - largest_free_block / free_bytes`, or `0.0` when `free_bytes` is zero.
+  ///
+  /// `0.0` means every free byte sits in one block; values close to `1.0` mean the free memory is scattered
+  /// over many small blocks and a large request may fail even though enough bytes are free in total.
+  pub external_fragmentation: f64,
+  pub live_allocations: usize,
+  /// Block nodes currently in use, one per used or free block.
+  pub live_nodes: usize,
+  /// Host bytes spent on bookkeeping: the block slab, bins, bitmaps and the offset index entries.
+  pub bookkeeping_bytes: usize,
+  pub node_allocations: u64,
+  pub node_frees: u64,
+  /// See [`SpeedAllocator::generation`].
+  pub generation: u64,
+  /// Allocations served from the request's own size class by the peek of [`FitPolicy::ExactFitFirst`] or
+  /// [`FitPolicy::TightFit`].
+  pub exact_fit_hits: u64,
+  /// Allocations served by the search starting at the first class whose every block fits.
+  pub rounded_fit_hits: u64,
+}
+
+/// Effective parameters of an allocator, see [`SpeedAllocator::config`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AllocatorConfig {
+  /// Sizes below `2^linear` are split into classes of equal width.
+  pub linear: u32,
+  /// Sub-bins per bin.
+  pub sub_bin: usize,
+  pub min_alloc_size: usize,
+  pub fit_policy: FitPolicy,
+  /// See [`SpeedAllocator::set_alignment_origin`].
+  pub alignment_origin: AlignmentOrigin,
+  /// Largest alignment every block start satisfies without padding.
+  pub min_alignment: usize,
+  /// Largest single allocation the pool could ever hold.
+  pub max_alloc_size: usize,
+  /// See [`SpeedAllocator::set_minimum_split_size`].
+  pub split_threshold: usize,
+  /// See [`SpeedAllocator::set_poison_on_free`].
+  pub poison_on_free: Option<u8>,
+  /// See [`SpeedAllocator::set_quarantine_depth`].
+  pub quarantine_depth: usize,
+}
+
+/// Allocation returned by [`SpeedAllocator::allocate_traced`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TracedAllocation {
+  pub ptr: NonNull<u8>,
+  /// Size class of the free block the allocation was carved from.
+  pub bin: BinRef,
+  /// The block came from a higher class than the first one guaranteed to fit the request, because every class
+  /// in between was empty.
+  pub overflowed: bool,
+}
+
+/// One live allocation, see [`SpeedAllocator::report_live_allocations`].
+#[derive(Clone, Debug)]
+pub struct AllocationReport {
+  pub offset: usize,
+  pub size: usize,
+  #[cfg_attr(
+    feature = "tagging",
+    doc = "Tag given to [`allocate_tagged`](SpeedAllocator::allocate_tagged), `0` for untagged allocations."
+  )]
+  #[cfg_attr(not(feature = "tagging"), doc = "Always `0`: tags need the `tagging` feature.")]
+  pub tag: u64,
+  /// Sequence number of the allocation, counting from 1 every allocation made since the allocator was created.
+  pub alloc_id: u64,
+  /// Stack captured when the allocation was made.
+  #[cfg(feature = "backtrace")]
+  pub backtrace: Arc<Backtrace>,
+}
+
+// Free list nodes `FitPolicy::TightFit` inspects per size class before settling.
+const TIGHT_FIT_SCAN: usize = 8;
+
+const DEFAULT_RESET_THRESHOLD: f64 = 0.5;
+
+/// How `allocate` picks among the free blocks able to hold a request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FitPolicy {
+  /// Takes the head of the first size class whose every block fits, without looking at individual blocks. O(1),
+  /// but skips blocks in the request's own class that would have fit.
+  #[default]
+  GoodFit,
+  /// First looks in the request's own class for a block that fits, then picks the smallest of the first few
+  /// blocks in the next non-empty class, trading a short list walk for less wasted space.
+  TightFit,
+  /// First looks in the request's own class for a block that fits, then falls back to [`GoodFit`]'s search.
+  /// Suits workloads of exact class-sized requests, which `GoodFit` would serve from the class above.
+  ///
+  /// [`GoodFit`]: FitPolicy::GoodFit
+  ExactFitFirst,
+}
+
+/// What allocation alignments are measured against, see [`SpeedAllocator::set_alignment_origin`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AlignmentOrigin {
+  /// The host address of the allocation: the returned pointers are aligned.
+  #[default]
+  Host,
+  /// The offset of the allocation from the pool start, for pools whose offsets are mapped onto another base
+  /// pointer known to be suitably aligned.
+  PoolRelative,
+  /// `base_offset + offset`, for a pool sitting `base_offset` bytes into a region whose base address is aligned
+  /// to `base_alignment`. Only alignments up to `base_alignment` can be guaranteed, larger ones are rejected.
+  Absolute { base_offset: usize, base_alignment: usize },
+}
+
+/// A TLSF allocator over one pool of host memory.
+///
+/// Every allocator carries the free list heads and occupancy bitmaps of all size classes up to `usize::MAX`,
+/// about 29 KiB on 64-bit targets, whatever the pool size. The overhead is intentional: with the tables sized
+/// at compile time, finding a class never needs a bounds check against a runtime length, and a small pool
+/// simply leaves the upper classes empty. Managing many tiny regions is better done by carving them out of
+/// one shared pool.
+///
+/// Cloning copies the pool contents into a new pool. Pointers handed out by the original don't point into the
+/// clone; its allocations sit at the same offsets of its own pool.
+pub struct SpeedAllocator {
+  pub(crate) pool: Pool,
+  pub(crate) blocks: Blocks,
+  pub(crate) head: BlockId,
+  pub(crate) tail: BlockId,
+  // The size classes are fixed at compile time, so the free list heads and bitmaps live inline instead of in
+  // separate heap allocations that `find_free_block` would have to chase.
+  pub(crate) bins: [Option<BlockId>; BLOCK_COUNT],
+  pub(crate) bin_bitmap: Bitmap,
+  pub(crate) sub_bin_bitmap: [Bitmap; BIN_COUNT],
+  pub(crate) allocations: BTreeMap<usize, BlockId>,
+  pub(crate) free_bytes: usize,
+  pub(crate) free_block_count: usize,
+  // free blocks per size class
+  pub(crate) class_counts: [usize; BLOCK_COUNT],
+  // free block size -> number of free blocks of that size, for the largest free block
+  pub(crate) free_sizes: BTreeMap<usize, usize>,
+  pub(crate) node_allocations: u64,
+  pub(crate) node_frees: u64,
+  pub(crate) exact_fit_hits: u64,
+  pub(crate) rounded_fit_hits: u64,
+  pub(crate) poison_on_free: Option<u8>,
+  // frees a block waits out in `quarantine` before it can be allocated again
+  pub(crate) quarantine_depth: usize,
+  // freed blocks held back from the free lists, oldest first
+  pub(crate) quarantine: VecDeque<BlockId>,
+  // smallest remainder worth splitting off into its own free block
+  pub(crate) split_threshold: usize,
+  pub(crate) fit_policy: FitPolicy,
+  pub(crate) alignment_origin: AlignmentOrigin,
+  // fragmentation at which `compute_optimal_reset_interval` expects a reset
+  pub(crate) reset_threshold: f64,
+  // bumped by every operation that changes the block layout
+  pub(crate) generation: u64,
+  // id handed to the next allocation
+  pub(crate) next_alloc_id: u64,
+  // bumped by `reset`, stamped into `Allocation` handles
+  pub(crate) epoch: u64,
+  // tells this allocator's `Allocation` handles apart from other allocators', unique per process
+  pub(crate) id: u64,
+  // bytes of the blocks of pinned allocations, at most `max_pinned_bytes`
+  pub(crate) pinned_bytes: usize,
+  pub(crate) max_pinned_bytes: usize,
+  // allocation id -> block of every live allocation `commit_plan` moved out of its original block, for `refresh`
+  pub(crate) relocated: HashMap<u64, BlockId>,
+  pub(crate) validation_cursor: Option<ValidationCursor>,
+  // set once the offset index is found out of sync with the blocks, fails every later operation; a `Cell` so
+  // that read-only lookups can set it too
+  pub(crate) poisoned: Cell<bool>,
+  // free list insertions and removals per size class, behind `monitor_bin_churn`
+  #[cfg(feature = "churn-monitoring")]
+  pub(crate) bin_insertions: Vec<u64>,
+  #[cfg(feature = "churn-monitoring")]
+  pub(crate) bin_removals: Vec<u64>,
+  // recent request sizes behind `allocation_size_profile`
+  #[cfg(feature = "profiling")]
+  pub(crate) size_window: SizeWindow,
+  // moving averages behind `demand_estimate`
+  #[cfg(feature = "perf-counters")]
+  pub(crate) demand: DemandTracker,
+  // utilization samples behind `utilization_over_time`
+  #[cfg(feature = "time-series")]
+  pub(crate) utilization: UtilizationSeries,
+  // tag -> offsets of the live allocations carrying it, behind `dealloc_all_tagged`
+  #[cfg(feature = "tagging")]
+  pub(crate) tagged: BTreeMap<u64, BTreeSet<usize>>,
 }
 
-impl TLSF {
-  fn new(size: usize) -> Self {
-    let initial_block = BlockHeader { size, free: true, next_free: None, prev_free: None };
-    TLSF { pool: vec![0; size], free_lists: vec![vec![initial_block]] }
+impl SpeedAllocator {
+  /// Creates an allocator over a fresh pool of `size` bytes, rounded down to a multiple of 8.
+  ///
+  /// # Panics
+  ///
+  /// If `size` is below 8 bytes, the smallest allocation the size classes can represent; see
+  /// [`try_new`](Self::try_new).
+  pub fn new(size: usize) -> Self {
+    match Self::try_new(size) {
+      Ok(allocator) => allocator,
+      Err(err) => panic!("{err}"),
+    }
+  }
+
+  /// Like [`new`](Self::new), but returns [`AllocError::PoolTooSmall`] for a pool below 8 bytes instead of
+  /// panicking.
+  pub fn try_new(size: usize) -> Result<Self, AllocError> {
+    if size < MIN_ALLOC_SIZE {
+      return Err(AllocError::PoolTooSmall { size });
+    }
+    let size = size - size % MIN_ALLOC_SIZE;
+    let mut blocks = Blocks::default();
+    let head = blocks.insert(Block::new(0, size));
+    let mut allocator = SpeedAllocator {
+      pool: Pool::new(size),
+      blocks,
+      head,
+      tail: head,
+      bins: [None; BLOCK_COUNT],
+      bin_bitmap: Bitmap::default(),
+      sub_bin_bitmap: [Bitmap::default(); BIN_COUNT],
+      allocations: BTreeMap::new(),
+      free_bytes: 0,
+      free_block_count: 0,
+      class_counts: [0; BLOCK_COUNT],
+      free_sizes: BTreeMap::new(),
+      node_allocations: 1,
+      node_frees: 0,
+      exact_fit_hits: 0,
+      rounded_fit_hits: 0,
+      poison_on_free: None,
+      quarantine_depth: 0,
+      quarantine: VecDeque::new(),
+      split_threshold: MIN_ALLOC_SIZE,
+      fit_policy: FitPolicy::GoodFit,
+      alignment_origin: AlignmentOrigin::Host,
+      reset_threshold: DEFAULT_RESET_THRESHOLD,
+      generation: 0,
+      next_alloc_id: 1,
+      epoch: 0,
+      id: next_allocator_id(),
+      pinned_bytes: 0,
+      max_pinned_bytes: usize::MAX,
+      relocated: HashMap::new(),
+      validation_cursor: None,
+      poisoned: Cell::new(false),
+      #[cfg(feature = "churn-monitoring")]
+      bin_insertions: vec![0; BLOCK_COUNT],
+      #[cfg(feature = "churn-monitoring")]
+      bin_removals: vec![0; BLOCK_COUNT],
+      #[cfg(feature = "profiling")]
+      size_window: SizeWindow::default(),
+      #[cfg(feature = "perf-counters")]
+      demand: DemandTracker::default(),
+      #[cfg(feature = "time-series")]
+      utilization: UtilizationSeries::default(),
+      #[cfg(feature = "tagging")]
+      tagged: BTreeMap::new(),
+    };
+    allocator.insert_free_block(head);
+    Ok(allocator)
+  }
+
+  /// Estimates the pool size needed to hold every `(size, alignment)` request of `workload` live at once, plus
+  /// `margin_percent` headroom, by replaying the requests in order against the free tail of a fresh allocator.
+  /// Only offsets are computed, no pool is allocated, so the workload may add up to more than the host has. A
+  /// request is only served from a free block spanning its whole size class, so the estimate is the furthest such
+  /// a class reaches from where the free tail starts, not just the end of the highest allocation. Requests
+  /// aligned to more than 8 bytes are counted with the most padding they can need, as where the pool will land
+  /// isn't known. Returns `usize::MAX` when the workload doesn't fit in the address space.
+  ///
+  /// # Panics
+  ///
+  /// If an alignment isn't a power of two.
+  pub fn estimate_required_pool_size(workload: &[(usize, usize)], margin_percent: u8) -> usize {
+    assert!(
+      workload.iter().all(|&(_, alignment)| alignment.is_power_of_two()),
+      "alignments must be powers of two"
+    );
+    // Each request is searched for as its size plus worst case padding rounded up to the next class, so room for
+    // every rounded size keeps the single free tail big enough for the next request until the last one.
+    let class_size = |size: usize, padding: usize| {
+      let search = size
+        .max(MIN_ALLOC_SIZE)
+        .checked_next_multiple_of(MIN_ALLOC_SIZE)
+        .map_or(usize::MAX, |size| size.saturating_add(padding));
+      binmap_up(search).map_or(search, |class| class.rounded_size())
+    };
+    // start of the free tail, which every request is carved from since nothing is freed
+    let mut tail: usize = 0;
+    let mut high_watermark = 0;
+    for &(size, alignment) in workload {
+      let padding = if alignment <= MIN_ALLOC_SIZE { 0 } else { alignment - 1 };
+      let class = class_size(size, padding);
+      high_watermark = high_watermark.max(tail.saturating_add(class));
+      let used = size
+        .max(MIN_ALLOC_SIZE)
+        .checked_next_multiple_of(MIN_ALLOC_SIZE)
+        .and_then(|size| size.checked_add(padding))
+        .and_then(|used| used.checked_next_multiple_of(MIN_ALLOC_SIZE));
+      tail = match used.and_then(|used| tail.checked_add(used)) {
+        Some(tail) => tail,
+        None => return usize::MAX,
+      };
+    }
+    // `high_watermark * scale / 100`, split so that only a result past `usize::MAX` overflows
+    let scale = 100 + margin_percent as usize;
+    (high_watermark / 100)
+      .checked_mul(scale)
+      .and_then(|size| size.checked_add(high_watermark % 100 * scale / 100))
+      .map_or(usize::MAX, |size| size.max(MIN_ALLOC_SIZE))
+  }
+
+  /// Like [`try_allocate`](Self::try_allocate), with every error mapped to `None` as `std` allocators do.
+  pub fn allocate(&mut self, size: usize, alignment: usize) -> Option<NonNull<u8>> {
+    self.try_allocate(size, alignment).ok()
+  }
+
+  /// Allocates `size` bytes aligned to `alignment`. Fails with [`AllocError::Poisoned`] while the allocator is
+  /// poisoned, [`AllocError::InvalidAlignment`] if `alignment` isn't a power of two and
+  /// [`AllocError::OutOfMemory`] if no free block holds the request.
+  pub fn try_allocate(&mut self, size: usize, alignment: usize) -> Result<NonNull<u8>, AllocError> {
+    self.check_request(alignment)?;
+    let Some((block, adjustment, rounded)) = self.preview_allocation(size, alignment) else {
+      #[cfg(feature = "perf-counters")]
+      self.demand.record_failure();
+      return Err(AllocError::OutOfMemory { size, alignment });
+    };
+    self.count_fit(block, rounded, alignment);
+    self.remove_free_block(block);
+    Ok(self.use_free_block(block, adjustment, rounded))
+  }
+
+  /// [`try_allocate`](Self::try_allocate) doing at most `op_budget` units of work, for callers that would rather
+  /// fail than stall. Fails with [`AllocError::BudgetExceeded`] once the budget runs out; the block search only
+  /// reads the free lists and the split is paid for before any block is taken out of them, so a failed call
+  /// leaves the allocator exactly as it was.
+  pub fn allocate_with_budget(
+    &mut self,
+    size: usize,
+    alignment: usize,
+    mut op_budget: OperationBudget,
+  ) -> Result<Allocation, AllocError> {
+    self.check_request(alignment)?;
+    let preview = self.preview_allocation_within(size, alignment, &mut op_budget);
+    if op_budget.is_exhausted() {
+      return Err(AllocError::BudgetExceeded);
+    }
+    let Some((block, adjustment, rounded)) = preview else {
+      #[cfg(feature = "perf-counters")]
+      self.demand.record_failure();
+      return Err(AllocError::OutOfMemory { size, alignment });
+    };
+    if self.blocks[block].size - used_size(adjustment, rounded) >= self.split_threshold && op_budget.spend().is_none() {
+      return Err(AllocError::BudgetExceeded);
+    }
+    self.count_fit(block, rounded, alignment);
+    self.remove_free_block(block);
+    let ptr = self.use_free_block(block, adjustment, rounded);
+    Ok(self.allocation_handle(ptr).expect("fresh allocation is indexed"))
+  }
+
+  // The checks every allocation makes before searching.
+  fn check_request(&self, alignment: usize) -> Result<(), AllocError> {
+    if self.poisoned.get() {
+      return Err(AllocError::Poisoned);
+    }
+    if !alignment.is_power_of_two() {
+      return Err(AllocError::InvalidAlignment { alignment });
+    }
+    if let AlignmentOrigin::Absolute { base_alignment, .. } = self.alignment_origin {
+      if alignment > base_alignment {
+        return Err(AllocError::AlignmentTooLarge { alignment, max: base_alignment });
+      }
+    }
+    Ok(())
+  }
+
+  /// Same as [`allocate`](Self::allocate), but also reports the size class of the free block that served the
+  /// request and whether it came from a class above the one the request maps to.
+  pub fn allocate_traced(&mut self, size: usize, alignment: usize) -> Option<TracedAllocation> {
+    if self.poisoned.get() {
+      return None;
+    }
+    let (block, adjustment, size) = self.preview_allocation(size, alignment)?;
+    let requested = binmap_up(size + self.alignment_padding(alignment))?;
+    let served = binmap_down(self.blocks[block].size);
+    self.count_fit(block, size, alignment);
+    self.remove_free_block(block);
+    Some(TracedAllocation {
+      ptr: self.use_free_block(block, adjustment, size),
+      bin: served,
+      overflowed: served.flat_index() > requested.flat_index(),
+    })
+  }
+
+  /// Makes `count` allocations of the same size and alignment, or none at all: if one fails, the ones already
+  /// made are freed and an empty `Vec` is returned. Once a size class is found to serve the request, the
+  /// following allocations keep taking blocks from its free list without searching the bitmaps again, until
+  /// the list runs dry.
+  pub fn allocate_burst(&mut self, count: usize, size: usize, alignment: usize) -> Vec<NonNull<u8>> {
+    let mut ptrs = Vec::with_capacity(count);
+    while ptrs.len() < count {
+      let preview = if self.poisoned.get() {
+        None
+      } else {
+        self.preview_allocation(size, alignment)
+      };
+      let Some((block, _, size)) = preview else {
+        for ptr in ptrs {
+          self.take_back(ptr);
+        }
+        return Vec::new();
+      };
+      let idx = binmap_down(self.blocks[block].size).flat_index();
+      let mut next = Some(block);
+      while let Some(block) = next.filter(|_| ptrs.len() < count) {
+        let adjustment = self.adjustment_in(block, alignment);
+        if used_size(adjustment, size) > self.blocks[block].size {
+          break;
+        }
+        self.count_fit(block, size, alignment);
+        self.remove_free_block(block);
+        ptrs.push(self.use_free_block(block, adjustment, size));
+        next = self.bins[idx];
+      }
+    }
+    ptrs
+  }
+
+  /// Allocates only from a free block the request fills closely enough that no remainder is split off, so no
+  /// block node is created. Returns `None` when every candidate would need a split, even if a larger block is
+  /// free. The first few blocks of each size class that could qualify are checked. The offset index entry is
+  /// still inserted and may allocate host memory.
+  pub fn allocate_no_split(&mut self, size: usize, alignment: usize) -> Option<NonNull<u8>> {
+    if self.poisoned.get() || !self.accepts_alignment(alignment) {
+      return None;
+    }
+    let size = size.max(MIN_ALLOC_SIZE).checked_next_multiple_of(MIN_ALLOC_SIZE)?;
+    let largest = size
+      .checked_add(self.alignment_padding(alignment))?
+      .checked_add(self.split_threshold - 1)?;
+    let last = binmap_down(largest).flat_index();
+    let (block, adjustment) = self
+      .occupied_classes_from(binmap_down(size))
+      .take_while(|&idx| idx <= last)
+      .find_map(|idx| {
+        let mut cursor = self.bins[idx];
+        (0..TIGHT_FIT_SCAN).find_map(|_| {
+          let id = cursor?;
+          cursor = self.blocks[id].next_free;
+          let adjustment = self.adjustment_in(id, alignment);
+          let spare = self.blocks[id].size.checked_sub(used_size(adjustment, size))?;
+          (spare < self.split_threshold).then_some((id, adjustment))
+        })
+      })?;
+    self.remove_free_block(block);
+    Some(self.use_free_block(block, adjustment, size))
+  }
+
+  /// Allocates at least `min_size` and preferably up to `max_size` bytes, e.g. for a buffer that can make do with
+  /// less. Takes the largest free block of at most `max_size` bytes that holds `min_size` after alignment, whole,
+  /// so no larger block gets split for it; the size classes that could hold such a block are walked in full.
+  /// Without one, allocates `max_size` bytes as [`allocate`](Self::allocate) would. Returns the pointer and the
+  /// usable size granted, which only exceeds `max_size` by slack too small to split off.
+  pub fn allocate_elastic(
+    &mut self,
+    min_size: usize,
+    max_size: usize,
+    alignment: usize,
+  ) -> Option<(NonNull<u8>, usize)> {
+    self.check_request(alignment).ok()?;
+    if min_size > max_size || !self.accepts_alignment(alignment) {
+      return None;
+    }
+    let min_size = min_size.max(MIN_ALLOC_SIZE);
+    let last = binmap_down(max_size).flat_index();
+    // (usable size, block, adjustment) of the best block so far
+    let mut best: Option<(usize, BlockId, usize)> = None;
+    for idx in self
+      .occupied_classes_from(binmap_down(min_size))
+      .take_while(|&idx| idx <= last)
+    {
+      let mut cursor = self.bins[idx];
+      while let Some(id) = cursor {
+        let block = &self.blocks[id];
+        cursor = block.next_free;
+        let adjustment = self.adjustment_in(id, alignment);
+        let usable = block.size.saturating_sub(adjustment);
+        if block.size <= max_size && usable >= min_size && best.is_none_or(|(size, ..)| usable > size) {
+          best = Some((usable, id, adjustment));
+        }
+      }
+    }
+    if let Some((usable, block, adjustment)) = best {
+      self.remove_free_block(block);
+      return Some((self.use_free_block(block, adjustment, usable), usable));
+    }
+    let ptr = self.try_allocate(max_size, alignment).ok()?;
+    let (_, size) = self.allocation_range(ptr)?;
+    Some((ptr, size))
+  }
+
+  /// Allocates right behind the live allocation at `prev`, e.g. to grow a buffer in place: succeeds only if the
+  /// block physically following `prev`'s is free and holds the request once aligned, and never falls back to
+  /// another block.
+  pub fn allocate_after(&mut self, prev: NonNull<u8>, size: usize, alignment: usize) -> Option<NonNull<u8>> {
+    if self.poisoned.get() || !self.accepts_alignment(alignment) {
+      return None;
+    }
+    let prev = self.allocation_block(prev).ok()?;
+    let next = self.blocks[prev]
+      .next_physical
+      .filter(|&next| self.blocks[next].is_free)?;
+    let size = size.max(MIN_ALLOC_SIZE).checked_next_multiple_of(MIN_ALLOC_SIZE)?;
+    let adjustment = self.adjustment_in(next, alignment);
+    if used_size(adjustment, size) > self.blocks[next].size {
+      return None;
+    }
+    self.remove_free_block(next);
+    Some(self.use_free_block(next, adjustment, size))
+  }
+
+  /// Allocates from the free block containing `hint_offset` when it holds the request once aligned, placing the
+  /// allocation at the start of that block, and otherwise falls back to [`allocate`](Self::allocate). Returns the
+  /// pointer along with the offset it was placed at, to keep related allocations close together.
+  pub fn allocate_with_placement_hint(
+    &mut self,
+    hint_offset: usize,
+    size: usize,
+    alignment: usize,
+  ) -> Option<(NonNull<u8>, usize)> {
+    if self.poisoned.get() || !self.accepts_alignment(alignment) {
+      return None;
+    }
+    let hinted = self.free_block_containing(hint_offset).and_then(|block| {
+      let size = size.max(MIN_ALLOC_SIZE).checked_next_multiple_of(MIN_ALLOC_SIZE)?;
+      let adjustment = self.adjustment_in(block, alignment);
+      (used_size(adjustment, size) <= self.blocks[block].size).then_some((block, adjustment, size))
+    });
+    let ptr = match hinted {
+      Some((block, adjustment, size)) => {
+        self.count_fit(block, size, alignment);
+        self.remove_free_block(block);
+        self.use_free_block(block, adjustment, size)
+      }
+      None => self.allocate(size, alignment)?,
+    };
+    Some((ptr, self.pool.offset_of(ptr)?))
+  }
+
+  // Free block covering `offset`. Free neighbours are merged, so the walk from the closest allocation at or
+  // before `offset` is usually one step.
+  fn free_block_containing(&self, offset: usize) -> Option<BlockId> {
+    let mut cursor = match self.allocations.range(..=offset).next_back() {
+      Some((_, &block)) => Some(block),
+      None => Some(self.head),
+    };
+    while let Some(id) = cursor {
+      let block = &self.blocks[id];
+      if block.offset > offset {
+        return None;
+      }
+      if offset < block.offset + block.size {
+        return (block.is_free && !block.drained).then_some(id);
+      }
+      cursor = block.next_physical;
+    }
+    None
+  }
+
+  // Moves the live allocation in `block` so that it starts at offset `to`, copying its data and keeping its id,
+  // tag and backtrace. The new block spans the granules the allocation touches and must lie in free memory or in
+  // the allocation's own block; otherwise nothing changes and `None` is returned. Nothing is poisoned or
+  // quarantined on the way, the old range is just free again.
+  pub(crate) fn move_allocation(&mut self, block: BlockId, to: usize) -> Option<NonNull<u8>> {
+    let old = self.blocks[block].clone();
+    let (from, size) = (old.offset + old.adjustment, old.size - old.adjustment);
+    let start = to - to % MIN_ALLOC_SIZE;
+    let end = to.checked_add(size)?.checked_next_multiple_of(MIN_ALLOC_SIZE)?;
+    if end > self.pool.len() {
+      return None;
+    }
+    self.allocations.remove(&from);
+    #[cfg(feature = "tagging")]
+    self.set_tag(block, None);
+    self.blocks[block].is_free = true;
+    let merged = self.merge_free_block(block);
+    self.insert_free_block(merged);
+    let (moved, offset) = match self.carve(start, end) {
+      Some(moved) => (moved, to),
+      None => {
+        let restored = self.carve(old.offset, old.offset + old.size);
+        (restored.expect("a block just freed can be taken back"), from)
+      }
+    };
+    let b = &mut self.blocks[moved];
+    b.is_free = false;
+    b.adjustment = offset - b.offset;
+    b.alloc_id = old.alloc_id;
+    #[cfg(feature = "backtrace")]
+    {
+      b.backtrace = old.backtrace;
+    }
+    #[cfg(feature = "tagging")]
+    self.set_tag(moved, old.tag);
+    self.allocations.insert(offset, moved);
+    if moved != block {
+      self.relocated.insert(old.alloc_id, moved);
+    } else {
+      self.relocated.remove(&old.alloc_id);
+    }
+    if offset != to {
+      return None;
+    }
+    self.pool.copy_within(from, to, size);
+    self.generation += 1;
+    Some(self.pool.ptr_at(to))
+  }
+
+  // Takes `[start, end)` out of the free block containing it, filing what's left on either side back into the
+  // free lists, and returns the block now spanning exactly that range, in no free list.
+  fn carve(&mut self, start: usize, end: usize) -> Option<BlockId> {
+    let mut block = self.free_block_containing(start)?;
+    let b = &self.blocks[block];
+    if end > b.offset + b.size {
+      return None;
+    }
+    self.remove_free_block(block);
+    if self.blocks[block].offset < start {
+      let rest = self.split_block(block, start - self.blocks[block].offset);
+      self.insert_free_block(block);
+      block = rest;
+    }
+    if end < self.blocks[block].offset + self.blocks[block].size {
+      let tail = self.split_block(block, end - start);
+      self.insert_free_block(tail);
+    }
+    Some(block)
+  }
+
+  /// [`allocate`](Self::allocate) with the size and alignment taken from `layout`.
+  pub fn allocate_layout(&mut self, layout: Layout) -> Option<NonNull<u8>> {
+    self.allocate(layout.size(), layout.align())
+  }
+
+  /// Frees an allocation made with [`allocate_layout`](Self::allocate_layout). `layout` is only used to
+  /// debug-assert that the allocation is large enough for it.
+  pub fn deallocate_layout(&mut self, ptr: NonNull<u8>, layout: Layout) {
+    debug_assert!(
+      self.allocation_range(ptr).is_none_or(|(_, len)| len >= layout.size()),
+      "allocation is smaller than its layout"
+    );
+    self.deallocate(ptr);
+  }
+
+  /// Allocates room for one `T` with its size and alignment taken from `Layout::new::<T>()`. The memory is not
+  /// initialized.
+  pub fn allocate_typed<T>(&mut self) -> Option<NonNull<T>> {
+    self.allocate_layout(Layout::new::<T>()).map(NonNull::cast)
+  }
+
+  pub fn deallocate_typed<T>(&mut self, ptr: NonNull<T>) {
+    self.deallocate(ptr.cast());
+  }
+
+  /// Retries a failed allocation up to `max_retries` times, calling `evict` before each retry so the caller can
+  /// free cached allocations. Gives up early once `evict` returns `false`, meaning nothing is left to evict.
+  pub fn allocate_or_evict(
+    &mut self,
+    size: usize,
+    alignment: usize,
+    mut evict: impl FnMut(&mut SpeedAllocator) -> bool,
+    max_retries: usize,
+  ) -> Option<NonNull<u8>> {
+    if let Some(ptr) = self.allocate(size, alignment) {
+      return Some(ptr);
+    }
+    for _ in 0..max_retries {
+      if !evict(self) {
+        return None;
+      }
+      if let Some(ptr) = self.allocate(size, alignment) {
+        return Some(ptr);
+      }
+    }
+    None
+  }
+
+  /// Whether `allocate(size, alignment)` would split its block and create a remainder node, or `None` if the
+  /// allocation would fail.
+  pub fn will_split(&self, size: usize, alignment: usize) -> Option<bool> {
+    let (block, adjustment, size) = self.preview_allocation(size, alignment)?;
+    Some(self.blocks[block].size - used_size(adjustment, size) >= self.split_threshold)
+  }
+
+  /// Like [`try_deallocate`](Self::try_deallocate), ignoring errors.
+  pub fn deallocate(&mut self, ptr: NonNull<u8>) {
+    let _ = self.try_deallocate(ptr);
+  }
+
+  /// Frees the allocation starting at `ptr`. If the offset index disagrees with the block it points to, the
+  /// allocator is poisoned and refuses further work until [`rebuild_index`](Self::rebuild_index) repairs it.
+  pub fn try_deallocate(&mut self, ptr: NonNull<u8>) -> Result<(), AllocError> {
+    let block = self.release_allocation(ptr)?;
+    if let Some(block) = self.quarantine(block) {
+      let block = self.merge_free_block(block);
+      self.insert_free_block(block);
+    }
+    self.generation += 1;
+    #[cfg(feature = "time-series")]
+    self.record_utilization();
+    Ok(())
+  }
+
+  // Frees an allocation the allocator made itself and is taking back, such as the first part of a burst that
+  // failed halfway. The block skips the quarantine, which only holds blocks the caller freed.
+  pub(crate) fn take_back(&mut self, ptr: NonNull<u8>) {
+    let Ok(block) = self.release_allocation(ptr) else {
+      return;
+    };
+    let block = self.merge_free_block(block);
+    self.insert_free_block(block);
+    self.generation += 1;
+    #[cfg(feature = "time-series")]
+    self.record_utilization();
+  }
+
+  /// Frees every allocation in `ptrs`, sorting them by address first so that neighbouring frees are merged
+  /// into one run as they go and the run only enters a free list once. Pointers that don't name a live
+  /// allocation are skipped; if the offset index turns out corrupted the allocator is poisoned and the rest of
+  /// the batch is left alone. Returns the number of merges performed.
+  pub fn batch_deallocate_sorted(&mut self, ptrs: &mut [NonNull<u8>]) -> usize {
+    ptrs.sort_unstable_by_key(|ptr| ptr.as_ptr() as usize);
+    let mut merges = 0;
+    let mut freed = false;
+    // freed run waiting to be merged with the next batch entry, not yet in a free list
+    let mut run: Option<BlockId> = None;
+    for &ptr in ptrs.iter() {
+      let block = match self.release_allocation(ptr) {
+        Ok(block) => block,
+        Err(AllocError::UnknownAllocation) => continue,
+        Err(_) => break,
+      };
+      freed = true;
+      let Some(block) = self.quarantine(block) else {
+        continue;
+      };
+      match run {
+        Some(current) if self.mergeable_prev(block) == Some(current) => {
+          self.absorb_next(current);
+          merges += 1;
+          continue;
+        }
+        Some(current) => merges += self.close_run(current),
+        None => {}
+      }
+      run = match self.mergeable_prev(block) {
+        Some(prev) => {
+          self.remove_free_block(prev);
+          self.absorb_next(prev);
+          merges += 1;
+          Some(prev)
+        }
+        None => Some(block),
+      };
+    }
+    if let Some(run) = run {
+      merges += self.close_run(run);
+    }
+    if freed {
+      self.generation += 1;
+      #[cfg(feature = "time-series")]
+      self.record_utilization();
+    }
+    merges
+  }
+
+  // Merges a freed run with a free block right after it and files it into its free list.
+  fn close_run(&mut self, run: BlockId) -> usize {
+    let next = self.mergeable_next(run);
+    if let Some(next) = next {
+      self.remove_free_block(next);
+      self.absorb_next(run);
+    }
+    self.insert_free_block(run);
+    next.is_some() as usize
+  }
+
+  // Checks `ptr` against the offset index and turns its block free, without merging it or filing it into a
+  // free list.
+  fn release_allocation(&mut self, ptr: NonNull<u8>) -> Result<BlockId, AllocError> {
+    let block = self.allocation_block(ptr)?;
+    let b = &self.blocks[block];
+    self.allocations.remove(&(b.offset + b.adjustment));
+    self.mark_free(block);
+    Ok(block)
+  }
+
+  // Points the index entry of the allocation at `ptr` at a neighbouring block, to test the corruption checks.
+  #[cfg(test)]
+  pub(crate) fn corrupt_index_entry(&mut self, ptr: NonNull<u8>) {
+    let offset = self.pool.offset_of(ptr).expect("pointer into the pool");
+    let b = &self.blocks[self.allocations[&offset]];
+    let other = b
+      .next_physical
+      .or(b.prev_physical)
+      .expect("a pool of more than one block");
+    self.allocations.insert(offset, other);
+  }
+
+  // `indexed_block` for the allocation starting at `ptr`.
+  pub(crate) fn allocation_block(&self, ptr: NonNull<u8>) -> Result<BlockId, AllocError> {
+    self.indexed_block(self.pool.offset_of(ptr).ok_or(AllocError::UnknownAllocation)?)
+  }
+
+  // Block of the live allocation starting at `offset`. Every lookup in the offset index goes through here: an
+  // entry naming a block that isn't a live allocation starting at `offset` poisons the allocator, so the
+  // corruption stops later operations instead of spreading.
+  pub(crate) fn indexed_block(&self, offset: usize) -> Result<BlockId, AllocError> {
+    if self.poisoned.get() {
+      return Err(AllocError::Poisoned);
+    }
+    let block = *self.allocations.get(&offset).ok_or(AllocError::UnknownAllocation)?;
+    match self.blocks.get(block) {
+      Some(b) if !b.is_free && !b.drained && !b.quarantined && b.offset + b.adjustment == offset => Ok(block),
+      _ => {
+        self.poisoned.set(true);
+        Err(AllocError::IndexCorrupted { offset })
+      }
+    }
+  }
+
+  /// Counter bumped by every successful operation that changes the block layout or the set of live
+  /// allocations: any allocation, any free (single, batched or by tag), `coalesce_all` and `defragment` when
+  /// they merge something, `split_at_power_of_two_boundaries`, `rebalance_large_free_blocks` and
+  /// `split_large_free_blocks` when they cut something, `pin` and `unpin` when they change a pin,
+  /// `drain_free_blocks`, `rebuild_index` and `reset`. Failed calls, queries and the `set_*` settings leave it
+  /// alone, except `set_quarantine_depth` when it releases blocks.
+  pub fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  /// Whether anything counted by [`generation`](Self::generation) happened since it returned `generation`.
+  pub fn has_changed_since(&self, generation: u64) -> bool {
+    self.generation != generation
   }
 
-  fn malloc(&mut self, size: usize) -> Option<*mut u8> {
-    let (fl, sl) = self.mapping(size);
-    for list in &mut self.free_lists {
-      if let Some(block) = list.iter_mut().find(|block| block.size >= size) {
-        block.free = false;
-        return Some(self.pool.as_mut_ptr().wrapping_add(block.size));
+  /// Frees every allocation at once, leaving the pool as one free block, and lifts the poison. Settings, the
+  /// hit counters and the allocation ids carry over. Every pointer handed out before is dangling afterwards;
+  /// [`Allocation`] handles made before report it through [`is_current`](Self::is_current).
+  pub fn reset(&mut self) {
+    let size = self.pool.len();
+    self.rebuild_layout([Block::new(0, size)]);
+    if let Some(byte) = self.poison_on_free {
+      self.pool.fill(0, size, byte);
+    }
+    self.poisoned.set(false);
+    self.epoch += 1;
+  }
+
+  // Replaces every block with `blocks`, which must be non-empty and cover the pool in offset order. Used blocks
+  // are indexed under fresh allocation ids, free blocks go into the free lists, quarantined ones into the
+  // quarantine in offset order and drained ones stay out of all of them.
+  pub(crate) fn rebuild_layout(&mut self, blocks: impl IntoIterator<Item = Block>) {
+    self.node_frees += self.live_nodes() as u64;
+    self.blocks = Blocks::default();
+    self.bins = [None; BLOCK_COUNT];
+    self.bin_bitmap = Bitmap::default();
+    self.sub_bin_bitmap = [Bitmap::default(); BIN_COUNT];
+    self.allocations.clear();
+    self.relocated.clear();
+    self.quarantine.clear();
+    self.pinned_bytes = 0;
+    self.free_bytes = 0;
+    self.free_block_count = 0;
+    self.class_counts = [0; BLOCK_COUNT];
+    self.free_sizes.clear();
+    #[cfg(feature = "tagging")]
+    self.tagged.clear();
+    let mut prev = None;
+    for block in blocks {
+      let id = self.blocks.insert(Block { prev_physical: prev, ..block });
+      self.node_allocations += 1;
+      match prev {
+        Some(prev) => self.blocks[prev].next_physical = Some(id),
+        None => self.head = id,
+      }
+      self.tail = id;
+      prev = Some(id);
+      let b = &mut self.blocks[id];
+      if b.is_free {
+        self.insert_free_block(id);
+      } else if b.quarantined {
+        self.quarantine.push_back(id);
+      } else if !b.drained {
+        b.alloc_id = self.next_alloc_id;
+        self.next_alloc_id += 1;
+        self.allocations.insert(b.offset + b.adjustment, id);
+        #[cfg(feature = "tagging")]
+        if let Some(tag) = b.tag {
+          self.tagged.entry(tag).or_default().insert(b.offset + b.adjustment);
+        }
       }
     }
+    debug_assert!(prev.is_some(), "rebuilt layout has no blocks");
+    self.validation_cursor = None;
+    self.generation += 1;
+  }
+
+  /// Number of [`reset`](Self::reset) calls so far.
+  pub fn epoch(&self) -> u64 {
+    self.epoch
+  }
+
+  /// Whether `allocation` was made since the last [`reset`](Self::reset). A stale handle may alias a newer
+  /// allocation at the same offset, so it must not be used or freed.
+  pub fn is_current(&self, allocation: &Allocation) -> bool {
+    allocation.epoch == self.epoch
+  }
+
+  pub fn is_poisoned(&self) -> bool {
+    self.poisoned.get()
+  }
+
+  /// Rebuilds the offset index from the physical chain and lifts the poison, the recovery path after
+  /// [`AllocError::IndexCorrupted`].
+  pub fn rebuild_index(&mut self) {
+    self.allocations.clear();
+    let mut cursor = Some(self.head);
+    while let Some(id) = cursor {
+      let block = &self.blocks[id];
+      if !block.is_free && !block.drained && !block.quarantined {
+        self.allocations.insert(block.offset + block.adjustment, id);
+      }
+      cursor = block.next_physical;
+    }
+    self.poisoned.set(false);
+    self.generation += 1;
+  }
+
+  /// Fills every freed block with `byte` (e.g. `0xDD`) so stale reads through dangling pointers stand out.
+  pub fn set_poison_on_free(&mut self, byte: Option<u8>) {
+    self.poison_on_free = byte;
+  }
+
+  /// Holds every freed block back from the free lists until `depth` newer frees have happened, so a dangling
+  /// pointer keeps reading the stale data (or the [poison](Self::set_poison_on_free)) instead of a newer
+  /// allocation, e.g. to catch use-after-free. Quarantined blocks count as used and don't merge with their
+  /// neighbours until they leave. A batch or tag-wide free counts one free per allocation. `0`, the default,
+  /// turns the quarantine off; lowering the depth releases the oldest blocks right away.
+  pub fn set_quarantine_depth(&mut self, depth: usize) {
+    self.quarantine_depth = depth;
+    if self.quarantine.len() > depth {
+      while self.quarantine.len() > depth {
+        let block = self
+          .quarantine
+          .pop_front()
+          .expect("quarantine is longer than its depth");
+        self.leave_quarantine(block);
+        let block = self.merge_free_block(block);
+        self.insert_free_block(block);
+      }
+      self.generation += 1;
+    }
+  }
+
+  /// Number of freed blocks waiting in the quarantine, see [`set_quarantine_depth`](Self::set_quarantine_depth).
+  pub fn quarantined_blocks(&self) -> usize {
+    self.quarantine.len()
+  }
+
+  /// Only splits a block when the remainder is at least `size` bytes (`MIN_ALLOC_SIZE` by default). A larger
+  /// threshold leaves the slack inside the allocation instead of creating tiny free fragments.
+  ///
+  /// Panics if `size` is smaller than `MIN_ALLOC_SIZE`, the smallest block the allocator can track.
+  pub fn set_minimum_split_size(&mut self, size: usize) {
+    assert!(
+      size >= MIN_ALLOC_SIZE,
+      "split threshold must be at least {MIN_ALLOC_SIZE} bytes"
+    );
+    self.split_threshold = size;
+  }
+
+  pub fn set_fit_policy(&mut self, policy: FitPolicy) {
+    self.fit_policy = policy;
+  }
+
+  /// Sets what allocations made from now on are aligned against, [`AlignmentOrigin::Host`] by default. Live
+  /// allocations keep the placement they got.
+  ///
+  /// # Panics
+  ///
+  /// If an [`AlignmentOrigin::Absolute`] `base_alignment` isn't a power of two.
+  pub fn set_alignment_origin(&mut self, origin: AlignmentOrigin) {
+    if let AlignmentOrigin::Absolute { base_alignment, .. } = origin {
+      assert!(
+        base_alignment.is_power_of_two(),
+        "base alignment {base_alignment} is not a power of two"
+      );
+    }
+    self.alignment_origin = origin;
+  }
+
+  /// The size class layout and the settings in effect, e.g. to record in a crash report.
+  pub fn config(&self) -> AllocatorConfig {
+    AllocatorConfig {
+      linear: LINEAR,
+      sub_bin: SUB_BIN,
+      min_alloc_size: MIN_ALLOC_SIZE,
+      fit_policy: self.fit_policy,
+      alignment_origin: self.alignment_origin,
+      min_alignment: self.natural_alignment(),
+      max_alloc_size: self.pool.len(),
+      split_threshold: self.split_threshold,
+      poison_on_free: self.poison_on_free,
+      quarantine_depth: self.quarantine_depth,
+    }
+  }
+
+  pub fn stats(&self) -> Stats {
+    let pool_size = self.pool.len();
+    let largest_free_block = self.largest_free_block();
+    let external_fragmentation = if self.free_bytes == 0 {
+      0.0
+    } else {
+      1.0 - largest_free_block as f64 / self.free_bytes as f64
+    };
+    Stats {
+      pool_size,
+      used_bytes: pool_size - self.free_bytes,
+      free_bytes: self.free_bytes,
+      free_block_count: self.free_block_count,
+      largest_free_block,
+      external_fragmentation,
+      live_allocations: self.allocations.len(),
+      live_nodes: self.live_nodes(),
+      bookkeeping_bytes: self.bookkeeping_bytes(),
+      node_allocations: self.node_allocations,
+      node_frees: self.node_frees,
+      generation: self.generation,
+      exact_fit_hits: self.exact_fit_hits,
+      rounded_fit_hits: self.rounded_fit_hits,
+    }
+  }
+
+  /// `bookkeeping_bytes` divided by the number of live allocations, or the whole figure when nothing is allocated.
+  pub fn bookkeeping_bytes_per_live_allocation(&self) -> f64 {
+    self.bookkeeping_bytes() as f64 / self.allocations.len().max(1) as f64
+  }
+
+  /// Bytes skipped at the start of live allocations to satisfy their alignment, summed over all of them. Counted
+  /// in `used_bytes`, but never handed out.
+  pub fn total_alignment_padding(&self) -> usize {
+    self
+      .allocations
+      .values()
+      .map(|&block| self.blocks[block].adjustment)
+      .sum()
+  }
+
+  fn live_nodes(&self) -> usize {
+    (self.node_allocations - self.node_frees) as usize
+  }
+
+  fn bookkeeping_bytes(&self) -> usize {
+    let bytes = self.blocks.slot_count() * size_of::<Block>()
+      + self.bins.len() * size_of::<Option<BlockId>>()
+      + size_of::<Bitmap>()
+      + self.sub_bin_bitmap.len() * size_of::<Bitmap>()
+      + self.allocations.len() * (size_of::<usize>() + size_of::<BlockId>())
+      + self.class_counts.len() * size_of::<usize>()
+      + self.free_sizes.len() * 2 * size_of::<usize>();
+    #[cfg(feature = "tagging")]
+    let bytes = bytes
+      + self
+        .tagged
+        .values()
+        .map(|offsets| size_of::<u64>() + offsets.len() * size_of::<usize>())
+        .sum::<usize>();
+    bytes
+  }
+
+  pub fn largest_free_block(&self) -> usize {
+    self.free_sizes.last_key_value().map_or(0, |(&size, _)| size)
+  }
+
+  /// Size of the free block starting exactly at `offset`, or 0 if no free block starts there. Free neighbours are
+  /// always merged, so this is the whole free run from `offset` unless a fenced cut ends the block early.
+  pub fn free_run_at(&self, offset: usize) -> usize {
+    self
+      .free_block_containing(offset)
+      .map(|id| &self.blocks[id])
+      .filter(|block| block.offset == offset)
+      .map_or(0, |block| block.size)
+  }
+
+  // A block of the largest free size, found in the highest non-empty sub-bin. The list is walked only up to
+  // the first block of that size, which is its head unless blocks of different sizes share the class.
+  fn largest_free_block_id(&self) -> Option<BlockId> {
+    let largest = self.largest_free_block();
+    let bin = self.bin_bitmap.last_set()?;
+    let sub_bin = self.sub_bin_bitmap[bin].last_set()?;
+    let mut cursor = self.bins[bin * SUB_BIN + sub_bin];
+    while let Some(id) = cursor {
+      if self.blocks[id].size == largest {
+        return Some(id);
+      }
+      cursor = self.blocks[id].next_free;
+    }
     None
   }
 
-  fn free(&mut self, ptr: *mut u8) {
-    todo!("implement free");
+  /// Number of free blocks that could each satisfy a request of `bytes` bytes on their own. Classes above the
+  /// one `bytes` falls in are counted whole from their counters; within that class, only the distinct free
+  /// block sizes are visited, not the blocks.
+  pub fn free_block_count_over(&self, bytes: usize) -> usize {
+    let class = binmap_down(bytes);
+    let sizes = match class.end() {
+      Some(end) => self.free_sizes.range(bytes..end),
+      None => self.free_sizes.range(bytes..),
+    };
+    let within: usize = sizes.map(|(_, &count)| count).sum();
+    let above: usize = self
+      .occupied_classes_from(class)
+      .filter(|&idx| idx != class.flat_index())
+      .map(|idx| self.class_counts[idx])
+      .sum();
+    within + above
+  }
+
+  /// Total size of the free blocks in first-level bin `bin_idx`, over all of its sub-bins. `0` for an empty or
+  /// out-of-range bin.
+  pub fn bin_free_bytes(&self, bin_idx: usize) -> usize {
+    if bin_idx >= BIN_COUNT {
+      return 0;
+    }
+    (0..SUB_BIN)
+      .flat_map(|sub_bin| self.free_list(bin_idx * SUB_BIN + sub_bin))
+      .map(|block| block.size)
+      .sum()
+  }
+
+  /// Total size of the free blocks in bin `min_bin` and every bin above it. Only the non-empty size classes are
+  /// visited.
+  pub fn total_free_bytes_in_bins_above(&self, min_bin: usize) -> usize {
+    if min_bin >= BIN_COUNT {
+      return 0;
+    }
+    self
+      .occupied_classes_from(BinRef::from_index(min_bin * SUB_BIN))
+      .flat_map(|idx| self.free_list(idx))
+      .map(|block| block.size)
+      .sum()
+  }
+
+  /// Share of the free blocks held by the fullest sub-bin, `0.0` without free blocks. Near `1.0` the free blocks
+  /// are almost all of one size class, a sign of a workload with little size diversity and a cue to call
+  /// [`rebalance_large_free_blocks`](Self::rebalance_large_free_blocks).
+  pub fn max_bin_load_factor(&self) -> f64 {
+    if self.free_block_count == 0 {
+      return 0.0;
+    }
+    let fullest = self
+      .occupied_classes_from(binmap_down(0))
+      .map(|idx| self.class_counts[idx])
+      .max()
+      .unwrap_or(0);
+    fullest as f64 / self.free_block_count as f64
+  }
+
+  /// Every free block of at least `min_size` bytes as `(offset, size)`, largest first and by offset among equal
+  /// sizes. Only the non-empty size classes from the one holding `min_size` upwards are visited, found through
+  /// the bitmaps.
+  pub fn find_all_free_blocks_above_size(&self, min_size: usize) -> Vec<(usize, usize)> {
+    let mut found: Vec<(usize, usize)> = self
+      .occupied_classes_from(binmap_down(min_size))
+      .flat_map(|idx| self.free_list(idx))
+      .filter(|block| block.size >= min_size)
+      .map(|block| (block.offset, block.size))
+      .collect();
+    found.sort_unstable_by_key(|&(offset, size)| (Reverse(size), offset));
+    found
+  }
+
+  /// Every free block as `(size, offset)`, largest first and by offset among equal sizes, gathered from the free
+  /// lists of the non-empty size classes.
+  pub fn free_blocks_sorted_by_size(&self) -> Vec<(usize, usize)> {
+    let mut found: Vec<(usize, usize)> = self
+      .occupied_classes_from(binmap_down(0))
+      .flat_map(|idx| self.free_list(idx))
+      .map(|block| (block.size, block.offset))
+      .collect();
+    found.sort_unstable_by_key(|&(size, offset)| (Reverse(size), offset));
+    found
+  }
+
+  // Flat indices of the non-empty size classes from `start` upwards, in increasing order.
+  fn occupied_classes_from(&self, start: BinRef) -> impl Iterator<Item = usize> + '_ {
+    let mut cursor = (start.bin(), start.sub_bin());
+    std::iter::from_fn(move || loop {
+      let bin = self.bin_bitmap.first_set_from(cursor.0)?;
+      let from = if bin == cursor.0 { cursor.1 } else { 0 };
+      match self.sub_bin_bitmap[bin].first_set_from(from) {
+        Some(sub_bin) => {
+          cursor = (bin, sub_bin + 1);
+          return Some(bin * SUB_BIN + sub_bin);
+        }
+        None => cursor = (bin + 1, 0),
+      }
+    })
+  }
+
+  /// Merges every run of neighbouring free blocks into one block. Frees merge eagerly, so this only finds work
+  /// after bulk operations that defer merging to a single pass.
+  pub fn coalesce_all(&mut self) {
+    let mut merged = false;
+    let mut cursor = Some(self.head);
+    while let Some(block) = cursor {
+      if self.blocks[block].is_free {
+        if self.is_in_free_list(block) {
+          self.remove_free_block(block);
+        }
+        while let Some(next) = self.mergeable_next(block) {
+          if self.is_in_free_list(next) {
+            self.remove_free_block(next);
+          }
+          self.absorb_next(block);
+          merged = true;
+        }
+        self.insert_free_block(block);
+      }
+      cursor = self.blocks[block].next_physical;
+    }
+    if merged {
+      self.generation += 1;
+    }
+  }
+
+  /// Runs [`coalesce_all`](Self::coalesce_all) and returns the resulting largest free block, to confirm how much
+  /// contiguous memory a merge pass recovered. Only already neighbouring free blocks are merged, nothing is
+  /// moved.
+  pub fn defragment(&mut self) -> usize {
+    self.coalesce_all();
+    self.largest_free_block()
   }
 
-  fn coalesce(&mut self, block: &mut BlockHeader) {
-    todo!("implement coalesce");
+  /// Largest free block [`coalesce_all`](Self::coalesce_all) would leave, without merging anything: the bytes
+  /// of the longest run of neighbouring free blocks, a fenced block starting a new run. Frees merge eagerly, so
+  /// anything above [`largest_free_block`](Self::largest_free_block) points at a missed merge.
+  pub fn largest_free_contiguous_run_after_coalesce(&self) -> usize {
+    let (mut largest, mut run) = (0, 0);
+    let mut cursor = Some(self.head);
+    while let Some(id) = cursor {
+      let block = &self.blocks[id];
+      run = match block.is_free {
+        true if block.fenced => block.size,
+        true => run + block.size,
+        false => 0,
+      };
+      largest = largest.max(run);
+      cursor = block.next_physical;
+    }
+    largest
   }
 
-  fn mapping(&self, size: usize) -> (usize, usize) {
-    todo!("implement mapping");
+  /// Splits every free block that crosses a multiple of `boundary` (a pool offset) at each such multiple, so no
+  /// allocation made from the pieces straddles one, e.g. a 512-byte block at offset 192 is cut at 256 and 512
+  /// for a 256-byte boundary. The cuts are permanent: the pieces are never merged back together, so later
+  /// requests larger than `boundary` can only be served from memory that was never split. Returns the number
+  /// of cuts.
+  ///
+  /// # Panics
+  ///
+  /// If `boundary` isn't a power of two of at least 8 bytes.
+  pub fn split_at_power_of_two_boundaries(&mut self, boundary: usize) -> usize {
+    assert!(
+      boundary.is_power_of_two() && boundary >= MIN_ALLOC_SIZE,
+      "boundary must be a power of two of at least {MIN_ALLOC_SIZE} bytes"
+    );
+    let mut splits = 0;
+    let mut cursor = Some(self.head);
+    while let Some(block) = cursor {
+      let (offset, size) = (self.blocks[block].offset, self.blocks[block].size);
+      let cut = (offset / boundary + 1) * boundary;
+      if self.blocks[block].is_free && cut < offset + size {
+        self.remove_free_block(block);
+        let rest = self.split_fenced(block, cut - offset);
+        self.insert_free_block(block);
+        self.insert_free_block(rest);
+        splits += 1;
+      }
+      cursor = self.blocks[block].next_physical;
+    }
+    if splits > 0 {
+      self.generation += 1;
+    }
+    splits
+  }
+
+  /// Cuts the largest free block into blocks of `target_size` bytes (rounded up to a multiple of 8) when it's at
+  /// least twice that size, the last piece keeping what's left over. Handing small requests ready-made blocks
+  /// instead of letting them all carve up one giant block spreads the free memory over the bins, but like
+  /// [`split_at_power_of_two_boundaries`](Self::split_at_power_of_two_boundaries) the pieces are never merged
+  /// back, so the largest possible allocation drops to `2 * target_size` or whatever other free block is
+  /// larger. Returns the number of blocks added by the cuts.
+  ///
+  /// # Panics
+  ///
+  /// If `target_size` is below 8 bytes.
+  pub fn rebalance_large_free_blocks(&mut self, target_size: usize) -> usize {
+    assert!(
+      target_size >= MIN_ALLOC_SIZE,
+      "target size must be at least {MIN_ALLOC_SIZE} bytes"
+    );
+    let target_size = target_size.next_multiple_of(MIN_ALLOC_SIZE);
+    let Some(mut block) = self.largest_free_block_id() else {
+      return 0;
+    };
+    if self.blocks[block].size / 2 < target_size {
+      return 0;
+    }
+    self.remove_free_block(block);
+    let mut created = 0;
+    while self.blocks[block].size / 2 >= target_size {
+      let rest = self.split_fenced(block, target_size);
+      self.insert_free_block(block);
+      block = rest;
+      created += 1;
+    }
+    self.insert_free_block(block);
+    self.generation += 1;
+    created
+  }
+
+  /// Cuts every free block larger than `max_size` (rounded down to a multiple of 8) into `ceil(size / max_size)`
+  /// pieces of at most `max_size` bytes, so one large request can't swallow memory many medium ones could use.
+  /// The pieces are fenced like the ones of
+  /// [`split_at_power_of_two_boundaries`](Self::split_at_power_of_two_boundaries) and never merge back, so
+  /// [`Stats::external_fragmentation`] rises and the cut memory can only serve requests whose rounded size fits
+  /// in one piece. Returns the number of cuts.
+  ///
+  /// # Panics
+  ///
+  /// If `max_size` is below 8 bytes.
+  pub fn split_large_free_blocks(&mut self, max_size: usize) -> usize {
+    assert!(
+      max_size >= MIN_ALLOC_SIZE,
+      "max size must be at least {MIN_ALLOC_SIZE} bytes"
+    );
+    let max_size = max_size - max_size % MIN_ALLOC_SIZE;
+    let mut splits = 0;
+    let mut cursor = Some(self.head);
+    while let Some(mut block) = cursor {
+      if self.blocks[block].is_free && !self.blocks[block].drained && self.blocks[block].size > max_size {
+        self.remove_free_block(block);
+        while self.blocks[block].size > max_size {
+          let rest = self.split_fenced(block, max_size);
+          self.insert_free_block(block);
+          block = rest;
+          splits += 1;
+        }
+        self.insert_free_block(block);
+      }
+      cursor = self.blocks[block].next_physical;
+    }
+    if splits > 0 {
+      self.generation += 1;
+    }
+    splits
+  }
+
+  /// Hands every free block to `sink(offset, size)` and takes it out of the free lists, e.g. so the caller can
+  /// unmap the backing memory. Drained blocks count as used from then on and are never allocated again.
+  pub fn drain_free_blocks(&mut self, mut sink: impl FnMut(usize, usize)) {
+    for idx in 0..BLOCK_COUNT {
+      while let Some(block) = self.bins[idx] {
+        self.remove_free_block(block);
+        let b = &mut self.blocks[block];
+        b.is_free = false;
+        b.drained = true;
+        sink(b.offset, b.size);
+      }
+    }
+    self.generation += 1;
+  }
+
+  /// Offset and usable size (the block minus its alignment padding) of the first live allocation starting at or
+  /// after `start_offset`.
+  pub fn find_allocation_after_offset(&self, start_offset: usize) -> Option<(usize, usize)> {
+    let (&offset, _) = self.allocations.range(start_offset..).next()?;
+    let b = &self.blocks[self.indexed_block(offset).ok()?];
+    Some((offset, b.size - b.adjustment))
+  }
+
+  /// Offset and usable size of every live allocation intersecting `[lo, hi)`, in offset order, e.g. to find the
+  /// allocations a write to that range has to wait on. Alignment padding doesn't count as part of an allocation.
+  /// An empty range, `lo >= hi`, overlaps nothing.
+  pub fn allocations_overlapping(&self, lo: usize, hi: usize) -> Vec<(usize, usize)> {
+    if lo >= hi {
+      return Vec::new();
+    }
+    self
+      .allocations
+      .range(..hi)
+      .map(|(&offset, &id)| (offset, self.blocks[id].offset + self.blocks[id].size - offset))
+      .filter(|&(offset, size)| offset + size > lo)
+      .collect()
+  }
+
+  /// One entry per `granularity`-sized slot of the pool, `true` when any used block overlaps the slot.
+  pub fn occupancy_map(&self, granularity: usize) -> Vec<bool> {
+    frozen::occupancy_map(self.blocks(), self.pool.len(), granularity)
+  }
+
+  /// Expected time until the pool should be reset, given `allocation_rate` and `deallocation_rate` per second,
+  /// under a simplified model where every allocation has the current average live size `s` (8 bytes when
+  /// nothing is live) and the pool holds `n = pool_size / s` of them:
+  ///
+  /// - each of the `min(allocation_rate, deallocation_rate)` replacements per second leaves a hole, so
+  ///   fragmentation approaches 1 as `f(t) = 1 - (1 - f0) * exp(-replacements * t / n)`, starting from the current
+  ///   [`Stats::external_fragmentation`] `f0`, and the reset is due when `f(t)` reaches the threshold set by
+  ///   [`set_reset_fragmentation_threshold`](Self::set_reset_fragmentation_threshold)
+  /// - when allocations outpace frees the net growth fills the free bytes, and the reset is due then if that
+  ///   comes first
+  ///
+  /// `Duration::ZERO` when the threshold is already reached, `Duration::MAX` when neither ever happens.
+  pub fn compute_optimal_reset_interval(&self, allocation_rate: f64, deallocation_rate: f64) -> Duration {
+    let (allocation_rate, deallocation_rate) = (allocation_rate.max(0.0), deallocation_rate.max(0.0));
+    let live_bytes = self.pool.len() - self.free_bytes;
+    let average = match self.allocations.len() {
+      0 => MIN_ALLOC_SIZE as f64,
+      live => live_bytes as f64 / live as f64,
+    };
+    let current = self.stats().external_fragmentation;
+    let threshold = self.reset_threshold;
+    if current >= threshold {
+      return Duration::ZERO;
+    }
+    let replacements = allocation_rate.min(deallocation_rate);
+    let until_fragmented = if replacements > 0.0 {
+      let capacity = self.pool.len() as f64 / average;
+      capacity / replacements * ((1.0 - current) / (1.0 - threshold)).ln()
+    } else {
+      f64::INFINITY
+    };
+    let growth = (allocation_rate - deallocation_rate) * average;
+    let until_full = if growth > 0.0 {
+      self.free_bytes as f64 / growth
+    } else {
+      f64::INFINITY
+    };
+    Duration::try_from_secs_f64(until_fragmented.min(until_full)).unwrap_or(Duration::MAX)
+  }
+
+  /// External fragmentation at which [`compute_optimal_reset_interval`](Self::compute_optimal_reset_interval)
+  /// considers a reset due, `0.5` by default.
+  ///
+  /// # Panics
+  ///
+  /// If `threshold` isn't in `0.0..1.0`.
+  pub fn set_reset_fragmentation_threshold(&mut self, threshold: f64) {
+    assert!(
+      (0.0..1.0).contains(&threshold),
+      "reset threshold {threshold} is outside 0.0..1.0"
+    );
+    self.reset_threshold = threshold;
+  }
+
+  /// [`Stats::external_fragmentation`] restricted to the free bytes inside `[lo, hi)`; free blocks straddling
+  /// the window only count with their part inside it.
+  pub fn fragmentation_in(&self, lo: usize, hi: usize) -> f32 {
+    let (mut largest, mut total) = (0, 0);
+    for block in self.blocks().filter(|block| block.is_free) {
+      let start = block.offset.max(lo);
+      let end = (block.offset + block.size).min(hi);
+      if start < end {
+        largest = largest.max(end - start);
+        total += end - start;
+      }
+    }
+    if total == 0 {
+      0.0
+    } else {
+      1.0 - largest as f32 / total as f32
+    }
+  }
+
+  /// Every live allocation in offset order, e.g. to assert in a test that nothing leaked once all expected frees
+  /// are done. With the `backtrace` feature each allocation captures its stack when made, which makes
+  /// allocating much slower.
+  pub fn report_live_allocations(&self) -> Vec<AllocationReport> {
+    let mut reports = Vec::with_capacity(self.allocations.len());
+    let mut cursor = Some(self.head);
+    while let Some(id) = cursor {
+      let block = &self.blocks[id];
+      let offset = block.offset + block.adjustment;
+      if !block.is_free && !block.drained && !block.quarantined {
+        reports.push(AllocationReport {
+          offset,
+          size: block.size - block.adjustment,
+          tag: block.tag(),
+          alloc_id: block.alloc_id,
+          #[cfg(feature = "backtrace")]
+          backtrace: block
+            .backtrace
+            .clone()
+            .unwrap_or_else(|| Arc::new(Backtrace::disabled())),
+        });
+      }
+      cursor = block.next_physical;
+    }
+    reports
+  }
+
+  /// Copies the block layout, live allocations and settings into an allocator over a fresh zeroed pool of the
+  /// same size, e.g. to get a shadow allocator with the same structure for test data. Free blocks are refilled
+  /// with the poison byte when [`set_poison_on_free`](Self::set_poison_on_free) is on.
+  pub fn clone_layout_without_data(&self) -> SpeedAllocator {
+    let mut shadow = self.with_pool(Pool::new(self.pool.len()));
+    if let Some(byte) = self.poison_on_free {
+      let mut cursor = Some(shadow.head);
+      while let Some(block) = cursor {
+        let b = &shadow.blocks[block];
+        if b.is_free {
+          shadow.pool.fill(b.offset, b.size, byte);
+        }
+        cursor = b.next_physical;
+      }
+    }
+    shadow
+  }
+
+  // Copy of the allocator's bookkeeping over `pool`.
+  fn with_pool(&self, pool: Pool) -> SpeedAllocator {
+    SpeedAllocator {
+      pool,
+      blocks: self.blocks.clone(),
+      head: self.head,
+      tail: self.tail,
+      bins: self.bins,
+      bin_bitmap: self.bin_bitmap,
+      sub_bin_bitmap: self.sub_bin_bitmap,
+      allocations: self.allocations.clone(),
+      free_bytes: self.free_bytes,
+      free_block_count: self.free_block_count,
+      class_counts: self.class_counts,
+      free_sizes: self.free_sizes.clone(),
+      node_allocations: self.node_allocations,
+      node_frees: self.node_frees,
+      exact_fit_hits: self.exact_fit_hits,
+      rounded_fit_hits: self.rounded_fit_hits,
+      poison_on_free: self.poison_on_free,
+      quarantine_depth: self.quarantine_depth,
+      quarantine: self.quarantine.clone(),
+      split_threshold: self.split_threshold,
+      fit_policy: self.fit_policy,
+      alignment_origin: self.alignment_origin,
+      reset_threshold: self.reset_threshold,
+      generation: self.generation,
+      next_alloc_id: self.next_alloc_id,
+      epoch: self.epoch,
+      id: next_allocator_id(),
+      pinned_bytes: self.pinned_bytes,
+      max_pinned_bytes: self.max_pinned_bytes,
+      relocated: self.relocated.clone(),
+      validation_cursor: self.validation_cursor,
+      poisoned: self.poisoned.clone(),
+      #[cfg(feature = "churn-monitoring")]
+      bin_insertions: self.bin_insertions.clone(),
+      #[cfg(feature = "churn-monitoring")]
+      bin_removals: self.bin_removals.clone(),
+      #[cfg(feature = "profiling")]
+      size_window: self.size_window.clone(),
+      #[cfg(feature = "perf-counters")]
+      demand: self.demand.clone(),
+      #[cfg(feature = "time-series")]
+      utilization: self.utilization.clone(),
+      #[cfg(feature = "tagging")]
+      tagged: self.tagged.clone(),
+    }
+  }
+
+  /// Estimates whether the free memory can hold every allocation in `sizes`, given as `(size, count)` pairs, by
+  /// allocating them largest first in a [layout copy](Self::clone_layout_without_data) of the allocator. The real
+  /// allocator is left untouched; the copy still allocates a pool of the same size, so this is meant for
+  /// planning rather than hot paths.
+  pub fn can_satisfy_distribution(&self, sizes: &[(usize, usize)]) -> bool {
+    let mut sizes = sizes.to_vec();
+    sizes.sort_unstable_by_key(|&(size, _)| Reverse(size));
+    let mut scratch = self.clone_layout_without_data();
+    sizes
+      .iter()
+      .all(|&(size, count)| (0..count).all(|_| scratch.allocate(size, 1).is_some()))
+  }
+
+  /// Checks that every free block sits in the bin `binmap_down` maps its size to, failing with
+  /// [`ValidationError::MisplacedFreeBlock`] for the first misplaced block. A misplaced block means
+  /// `insert_free_block` picked the wrong size class.
+  pub fn validate_all_free_blocks_in_correct_bins(&self) -> Result<(), ValidationError> {
+    for idx in 0..BLOCK_COUNT {
+      for block in self.free_list(idx) {
+        let expected_bin = binmap_down(block.size);
+        if expected_bin.flat_index() != idx {
+          return Err(ValidationError::MisplacedFreeBlock {
+            offset: block.offset,
+            size: block.size,
+            bin: BinRef::from_index(idx),
+            expected_bin,
+          });
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Recomputes the bin and sub-bin bitmaps from the undrained free blocks of the physical chain, overwriting the
+  /// stored ones, and returns the number of bits that were wrong. Any non-zero result points at a bug in the
+  /// bitmap upkeep; the repair only helps if the free lists themselves are intact.
+  pub fn repair_bitmaps(&mut self) -> usize {
+    let mut bin_bitmap = Bitmap::default();
+    let mut sub_bin_bitmap = [Bitmap::default(); BIN_COUNT];
+    let mut cursor = Some(self.head);
+    while let Some(id) = cursor {
+      let block = &self.blocks[id];
+      if block.is_free && !block.drained {
+        let map = binmap_down(block.size);
+        bin_bitmap.set(map.bin());
+        sub_bin_bitmap[map.bin()].set(map.sub_bin());
+      }
+      cursor = block.next_physical;
+    }
+    let corrected = self.bin_bitmap.count_differing(&bin_bitmap)
+      + (self.sub_bin_bitmap.iter().zip(&sub_bin_bitmap))
+        .map(|(stored, expected)| stored.count_differing(expected))
+        .sum::<usize>();
+    self.bin_bitmap = bin_bitmap;
+    self.sub_bin_bitmap = sub_bin_bitmap;
+    corrected
+  }
+
+  /// Writes `pattern` over the whole allocation at `ptr`, repeating it and truncating the last repetition, then
+  /// reads it back. Returns `false` if any byte differs, if `ptr` isn't a live allocation, or if `pattern` is
+  /// empty and so checks nothing. Deterministic fills make partial writes and aliasing allocations visible in
+  /// stress tests.
+  pub fn fill_allocation_pattern_and_check(&mut self, ptr: NonNull<u8>, pattern: &[u8]) -> bool {
+    let Some((offset, len)) = self.allocation_range(ptr).filter(|_| !pattern.is_empty()) else {
+      return false;
+    };
+    self.pool.fill_pattern(offset, len, pattern);
+    self.pool.count_mismatches(offset, len, pattern) == 0
+  }
+
+  /// Number of bytes of the allocation at `ptr` that no longer hold `pattern` as written by
+  /// [`fill_allocation_pattern_and_check`](Self::fill_allocation_pattern_and_check), or `None` if `ptr` isn't a
+  /// live allocation or `pattern` is empty.
+  pub fn check_result(&self, ptr: NonNull<u8>, pattern: &[u8]) -> Option<usize> {
+    let (offset, len) = self.allocation_range(ptr).filter(|_| !pattern.is_empty())?;
+    Some(self.pool.count_mismatches(offset, len, pattern))
+  }
+
+  // Offset and usable length of the live allocation starting at `ptr`.
+  pub(crate) fn allocation_range(&self, ptr: NonNull<u8>) -> Option<(usize, usize)> {
+    let block = &self.blocks[self.allocation_block(ptr).ok()?];
+    Some((block.offset + block.adjustment, block.size - block.adjustment))
+  }
+
+  // Handle of the live allocation starting at `ptr`, remembering its block for `resolve_handle`.
+  pub(crate) fn allocation_handle(&self, ptr: NonNull<u8>) -> Option<Allocation> {
+    let block = self.allocation_block(ptr).ok()?;
+    let b = &self.blocks[block];
+    Some(Allocation {
+      ptr,
+      size: b.size - b.adjustment,
+      epoch: self.epoch,
+      allocator_id: self.id,
+      block,
+      alloc_id: b.alloc_id,
+    })
+  }
+
+  /// Block of the allocation behind `allocation`, read straight from the block the handle remembers instead of
+  /// looking the offset up in the index, so it takes constant time. Fails with [`AllocError::UnknownAllocation`]
+  /// once the allocation is freed, for a handle that predates a [`reset`](Self::reset) and for a handle of
+  /// another allocator. The handle of an allocation moved by [`commit_plan`](Self::commit_plan) needs a
+  /// [`refresh`](Self::refresh) first.
+  pub fn resolve_handle(&self, allocation: &Allocation) -> Result<BlockInfo, AllocError> {
+    let block = self.handle_block(allocation).ok_or(AllocError::UnknownAllocation)?;
+    Ok(BlockInfo::new(&self.blocks[block]))
+  }
+
+  /// Points `allocation` at where its allocation lives now, after [`commit_plan`](Self::commit_plan) moved it,
+  /// so that [`resolve_handle`](Self::resolve_handle) works on it again. Takes constant time like
+  /// `resolve_handle`; the offset index isn't consulted. Fails with [`AllocError::UnknownAllocation`] once the
+  /// allocation is freed, and for handles `resolve_handle` rejects for their epoch or allocator.
+  pub fn refresh(&self, allocation: &mut Allocation) -> Result<(), AllocError> {
+    if allocation.allocator_id != self.id || !self.is_current(allocation) {
+      return Err(AllocError::UnknownAllocation);
+    }
+    let block = match self.handle_block(allocation) {
+      Some(block) => block,
+      None => *self
+        .relocated
+        .get(&allocation.alloc_id)
+        .ok_or(AllocError::UnknownAllocation)?,
+    };
+    let b = &self.blocks[block];
+    allocation.ptr = self.pool.ptr_at(b.offset + b.adjustment);
+    allocation.size = b.size - b.adjustment;
+    allocation.block = block;
+    Ok(())
+  }
+
+  // Block `allocation` remembers, if this allocator made the handle in its current epoch and the block still
+  // holds that allocation.
+  pub(crate) fn handle_block(&self, allocation: &Allocation) -> Option<BlockId> {
+    let block = self.blocks.get(allocation.block)?;
+    let current = allocation.allocator_id == self.id && self.is_current(allocation);
+    let live = !block.is_free && !block.quarantined && !block.drained && block.alloc_id == allocation.alloc_id;
+    (current && live).then_some(allocation.block)
+  }
+
+  /// Runs Floyd's cycle detection over every free list, failing with [`ValidationError::FreeListCycle`] for the
+  /// first size class whose `next_free` chain loops back on itself. Such a list would make `find_free_block` and
+  /// the free list walks spin forever.
+  pub fn verify_no_cycles_in_free_list(&self) -> Result<(), ValidationError> {
+    for idx in 0..BLOCK_COUNT {
+      let next = |id: BlockId| self.blocks[id].next_free;
+      let (mut slow, mut fast) = (self.bins[idx], self.bins[idx]);
+      while let Some(id) = fast.and_then(next).and_then(next) {
+        slow = slow.and_then(next);
+        fast = Some(id);
+        if slow == fast {
+          return Err(ValidationError::FreeListCycle { bin: BinRef::from_index(idx) });
+        }
+      }
+    }
+    Ok(())
+  }
+
+  /// Metadata of the allocation starting at `ptr`, or `None` if `ptr` isn't the start of a live allocation or
+  /// the allocator is poisoned. An index entry found out of sync with its block poisons the allocator.
+  pub fn get_block_info(&self, ptr: NonNull<u8>) -> Option<BlockInfo> {
+    let block = self.allocation_block(ptr).ok()?;
+    Some(BlockInfo::new(&self.blocks[block]))
+  }
+
+  /// Walks every block in offset order, see [`PhysicalBlockIter`].
+  pub fn blocks(&self) -> PhysicalBlockIter<'_> {
+    PhysicalBlockIter::new(&self.blocks, self.head, self.tail)
+  }
+
+  /// Walks every block from the highest offset down to the pool start.
+  pub fn physical_chain_reverse_iterator(&self) -> impl Iterator<Item = BlockInfo> + '_ {
+    self.blocks().rev()
+  }
+
+  // Every block offset is a multiple of `MIN_ALLOC_SIZE`, so smaller alignments may hold without padding.
+  fn natural_alignment(&self) -> usize {
+    let origin = 1 << self.alignment_base().trailing_zeros().min(usize::BITS - 1);
+    match self.alignment_origin {
+      AlignmentOrigin::Absolute { base_alignment, .. } => origin.min(base_alignment).min(MIN_ALLOC_SIZE),
+      _ => origin.min(MIN_ALLOC_SIZE),
+    }
+  }
+
+  // What block offsets are added to before aligning them.
+  pub(crate) fn alignment_base(&self) -> usize {
+    match self.alignment_origin {
+      AlignmentOrigin::Host => self.pool.address(),
+      AlignmentOrigin::PoolRelative => 0,
+      AlignmentOrigin::Absolute { base_offset, .. } => base_offset,
+    }
+  }
+
+  fn accepts_alignment(&self, alignment: usize) -> bool {
+    alignment.is_power_of_two()
+      && match self.alignment_origin {
+        AlignmentOrigin::Absolute { base_alignment, .. } => alignment <= base_alignment,
+        _ => true,
+      }
+  }
+
+  pub(crate) fn free_list(&self, idx: usize) -> impl Iterator<Item = &Block> {
+    let mut cursor = self.bins[idx];
+    std::iter::from_fn(move || {
+      let block = &self.blocks[cursor?];
+      cursor = block.next_free;
+      Some(block)
+    })
+  }
+
+  // Picks the block `allocate` would use along with its alignment adjustment and the rounded request size.
+  fn preview_allocation(&self, size: usize, alignment: usize) -> Option<(BlockId, usize, usize)> {
+    self.preview_allocation_within(size, alignment, &mut OperationBudget::new(u64::MAX))
+  }
+
+  // `preview_allocation` charging its search to `budget`; `None` as well once the budget is exhausted.
+  fn preview_allocation_within(
+    &self,
+    size: usize,
+    alignment: usize,
+    budget: &mut OperationBudget,
+  ) -> Option<(BlockId, usize, usize)> {
+    if !self.accepts_alignment(alignment) {
+      return None;
+    }
+    let size = size.max(MIN_ALLOC_SIZE).checked_next_multiple_of(MIN_ALLOC_SIZE)?;
+    let block = self.find_free_block(size.checked_add(self.alignment_padding(alignment))?, budget)?;
+    Some((block, self.adjustment_in(block, alignment), size))
+  }
+
+  // Bytes to skip at the start of `block` to reach `alignment`.
+  fn adjustment_in(&self, block: BlockId, alignment: usize) -> usize {
+    let start = self.alignment_base().wrapping_add(self.blocks[block].offset);
+    start.wrapping_neg() & (alignment - 1)
+  }
+
+  // Counts whether `block` came from below the rounded-up search class of a `size`-byte request, i.e. from the
+  // exact class peek.
+  fn count_fit(&mut self, block: BlockId, size: usize, alignment: usize) {
+    let served = binmap_down(self.blocks[block].size).flat_index();
+    match binmap_up(size + self.alignment_padding(alignment)) {
+      Some(search) if served < search.flat_index() => self.exact_fit_hits += 1,
+      _ => self.rounded_fit_hits += 1,
+    }
+  }
+
+  // Worst-case bytes needed in front of a block to reach `alignment`.
+  fn alignment_padding(&self, alignment: usize) -> usize {
+    if alignment <= self.natural_alignment() {
+      0
+    } else {
+      alignment - 1
+    }
+  }
+
+  // Every bitmap scanned and free-list node visited takes a unit of `budget`.
+  fn find_free_block(&self, size: usize, budget: &mut OperationBudget) -> Option<BlockId> {
+    if self.fit_policy != FitPolicy::GoodFit {
+      let exact = binmap_down(size).flat_index();
+      if let Some(block) = self.smallest_fitting(exact, size, budget) {
+        return Some(block);
+      }
+    }
+    let map = binmap_up(size)?;
+    budget.spend()?;
+    let (bin, sub_bin) = match self.sub_bin_bitmap[map.bin()].first_set_from(map.sub_bin()) {
+      Some(sub_bin) => (map.bin(), sub_bin),
+      // the top bin has no higher bin to overflow into
+      None if map.bin() + 1 == BIN_COUNT => return None,
+      None => {
+        budget.spend()?;
+        let bin = self.bin_bitmap.first_set_from(map.bin() + 1)?;
+        budget.spend()?;
+        (bin, self.sub_bin_bitmap[bin].first_set_from(0)?)
+      }
+    };
+    let idx = bin * SUB_BIN + sub_bin;
+    match self.fit_policy {
+      FitPolicy::GoodFit | FitPolicy::ExactFitFirst => budget.spend().and(self.bins[idx]),
+      FitPolicy::TightFit => self.smallest_fitting(idx, size, budget),
+    }
+  }
+
+  // Smallest block of at least `size` bytes among the first `TIGHT_FIT_SCAN` blocks of a free list.
+  fn smallest_fitting(&self, idx: usize, size: usize, budget: &mut OperationBudget) -> Option<BlockId> {
+    let mut best: Option<(usize, BlockId)> = None;
+    let mut cursor = self.bins[idx];
+    for _ in 0..TIGHT_FIT_SCAN {
+      let Some(id) = cursor else {
+        break;
+      };
+      budget.spend()?;
+      let block = &self.blocks[id];
+      if block.size >= size && best.is_none_or(|(best_size, _)| block.size < best_size) {
+        best = Some((block.size, id));
+      }
+      cursor = block.next_free;
+    }
+    best.map(|(_, id)| id)
+  }
+
+  fn insert_free_block(&mut self, block: BlockId) {
+    let size = self.blocks[block].size;
+    let map = binmap_down(size);
+    let idx = map.flat_index();
+    let head = self.bins[idx];
+    let b = &mut self.blocks[block];
+    b.prev_free = None;
+    b.next_free = head;
+    if let Some(next) = head {
+      self.blocks[next].prev_free = Some(block);
+    }
+    self.bins[idx] = Some(block);
+    #[cfg(feature = "churn-monitoring")]
+    {
+      self.bin_insertions[idx] += 1;
+    }
+    self.bin_bitmap.set(map.bin());
+    self.sub_bin_bitmap[map.bin()].set(map.sub_bin());
+    self.free_bytes += size;
+    self.free_block_count += 1;
+    self.class_counts[idx] += 1;
+    *self.free_sizes.entry(size).or_default() += 1;
+  }
+
+  fn remove_free_block(&mut self, block: BlockId) {
+    let b = &mut self.blocks[block];
+    let (size, prev_free, next_free) = (b.size, b.prev_free, b.next_free);
+    b.prev_free = None;
+    b.next_free = None;
+    let map = binmap_down(size);
+    let idx = map.flat_index();
+    debug_assert!(
+      self.sub_bin_bitmap[map.bin()].test(map.sub_bin()),
+      "removing a block from an empty size class"
+    );
+    match prev_free {
+      Some(prev) => self.blocks[prev].next_free = next_free,
+      None => self.bins[idx] = next_free,
+    }
+    if let Some(next) = next_free {
+      self.blocks[next].prev_free = prev_free;
+    }
+    #[cfg(feature = "churn-monitoring")]
+    {
+      self.bin_removals[idx] += 1;
+    }
+    if self.bins[idx].is_none() {
+      self.sub_bin_bitmap[map.bin()].clear(map.sub_bin());
+      if self.sub_bin_bitmap[map.bin()].is_empty() {
+        self.bin_bitmap.clear(map.bin());
+      }
+    }
+    self.free_bytes -= size;
+    self.free_block_count -= 1;
+    self.class_counts[idx] -= 1;
+    match self.free_sizes.get_mut(&size) {
+      Some(count) if *count > 1 => *count -= 1,
+      _ => {
+        self.free_sizes.remove(&size);
+      }
+    }
+  }
+
+  // Flags a used block as free without merging it or putting it in a free list yet.
+  pub(crate) fn mark_free(&mut self, block: BlockId) {
+    #[cfg(feature = "tagging")]
+    self.set_tag(block, None);
+    let b = &mut self.blocks[block];
+    if !self.relocated.is_empty() {
+      self.relocated.remove(&b.alloc_id);
+    }
+    if b.pinned {
+      b.pinned = false;
+      self.pinned_bytes -= b.size;
+    }
+    b.is_free = true;
+    b.adjustment = 0;
+    b.alloc_id = 0;
+    #[cfg(feature = "backtrace")]
+    {
+      b.backtrace = None;
+    }
+    if let Some(byte) = self.poison_on_free {
+      self.pool.fill(b.offset, b.size, byte);
+    }
+    #[cfg(feature = "perf-counters")]
+    self.demand.record_free();
+  }
+
+  // Puts a block just released by `mark_free` in the quarantine, returning the block to merge and file into the
+  // free lists now: `block` itself when the quarantine is off, the oldest quarantined block once the quarantine
+  // holds more than `quarantine_depth` blocks, or nothing.
+  pub(crate) fn quarantine(&mut self, block: BlockId) -> Option<BlockId> {
+    if self.quarantine_depth == 0 {
+      return Some(block);
+    }
+    let b = &mut self.blocks[block];
+    b.is_free = false;
+    b.quarantined = true;
+    self.quarantine.push_back(block);
+    if self.quarantine.len() <= self.quarantine_depth {
+      return None;
+    }
+    let oldest = self.quarantine.pop_front()?;
+    self.leave_quarantine(oldest);
+    Some(oldest)
+  }
+
+  fn leave_quarantine(&mut self, block: BlockId) {
+    let b = &mut self.blocks[block];
+    b.is_free = true;
+    b.quarantined = false;
+  }
+
+  fn is_in_free_list(&self, block: BlockId) -> bool {
+    let b = &self.blocks[block];
+    b.prev_free.is_some() || self.bins[binmap_down(b.size).flat_index()] == Some(block)
+  }
+
+  // Marks a block taken out of the free lists as used, returning its tail to the free lists when it's big enough.
+  fn use_free_block(&mut self, block: BlockId, adjustment: usize, size: usize) -> NonNull<u8> {
+    let used = used_size(adjustment, size);
+    let (offset, block_size) = (self.blocks[block].offset, self.blocks[block].size);
+    if block_size - used >= self.split_threshold {
+      let remainder = self.split_block(block, used);
+      let r = &self.blocks[remainder];
+      debug_assert!(
+        r.offset + r.size <= self.pool.len(),
+        "split remainder runs past the pool end"
+      );
+      debug_assert!(
+        offset + used <= r.offset,
+        "split remainder overlaps the allocated block"
+      );
+      self.insert_free_block(remainder);
+    }
+    let b = &mut self.blocks[block];
+    b.is_free = false;
+    b.adjustment = adjustment;
+    b.alloc_id = self.next_alloc_id;
+    #[cfg(feature = "backtrace")]
+    {
+      b.backtrace = Some(Arc::new(Backtrace::force_capture()));
+    }
+    self.next_alloc_id += 1;
+    #[cfg(feature = "profiling")]
+    self.size_window.record(size, self.blocks[block].size);
+    #[cfg(feature = "perf-counters")]
+    self.demand.record_allocation(size);
+    self.allocations.insert(offset + adjustment, block);
+    self.generation += 1;
+    #[cfg(feature = "time-series")]
+    self.record_utilization();
+    self.pool.ptr_at(offset + adjustment)
+  }
+
+  // Cuts `block` after its first `size` bytes, returning the new block holding the rest. Neither block is put in
+  // a free list.
+  fn split_block(&mut self, block: BlockId, size: usize) -> BlockId {
+    let rest = self.blocks[block].split_off(size);
+    let next_physical = self.blocks[block].next_physical;
+    let rest = self.blocks.insert(rest);
+    self.node_allocations += 1;
+    let r = &mut self.blocks[rest];
+    r.prev_physical = Some(block);
+    r.next_physical = next_physical;
+    match next_physical {
+      Some(next) => self.blocks[next].prev_physical = Some(rest),
+      None => self.tail = rest,
+    }
+    self.blocks[block].next_physical = Some(rest);
+    rest
+  }
+
+  // `split_block` whose second half is never merged back into the first.
+  fn split_fenced(&mut self, block: BlockId, size: usize) -> BlockId {
+    let rest = self.split_block(block, size);
+    self.blocks[rest].fenced = true;
+    rest
+  }
+
+  // Folds free physical neighbours into `block`, returning the surviving (lowest) block.
+  fn merge_free_block(&mut self, mut block: BlockId) -> BlockId {
+    // a pool made of a single block has no neighbour to merge with
+    if self.head == self.tail {
+      return block;
+    }
+    debug_assert_ne!(
+      self.blocks[block].next_physical,
+      Some(block),
+      "block is its own next physical neighbour"
+    );
+    if let Some(next) = self.mergeable_next(block) {
+      self.remove_free_block(next);
+      self.absorb_next(block);
+    }
+    debug_assert_ne!(
+      self.blocks[block].prev_physical,
+      Some(block),
+      "block is its own previous physical neighbour"
+    );
+    if let Some(prev) = self.mergeable_prev(block) {
+      self.remove_free_block(prev);
+      self.absorb_next(prev);
+      block = prev;
+    }
+    block
+  }
+
+  // Free block right after `block` that may be folded into it.
+  fn mergeable_next(&self, block: BlockId) -> Option<BlockId> {
+    let next = self.blocks[block].next_physical?;
+    let n = &self.blocks[next];
+    (n.is_free && !n.fenced).then_some(next)
+  }
+
+  // Free block right before `block` that `block` may be folded into.
+  fn mergeable_prev(&self, block: BlockId) -> Option<BlockId> {
+    let b = &self.blocks[block];
+    b.prev_physical.filter(|&prev| !b.fenced && self.blocks[prev].is_free)
+  }
+
+  // Grows `block` over its next physical neighbour and releases the neighbour's node.
+  fn absorb_next(&mut self, block: BlockId) {
+    let Some(next) = self.blocks[block].next_physical else {
+      return;
+    };
+    let next = self.blocks.release(next);
+    let after = next.next_physical;
+    self.blocks[block].absorb(next);
+    match after {
+      Some(after) => self.blocks[after].prev_physical = Some(block),
+      None => self.tail = block,
+    }
+    self.node_frees += 1;
+  }
+}
+
+impl Clone for SpeedAllocator {
+  fn clone(&self) -> Self {
+    self.with_pool(self.pool.clone())
+  }
+}
+
+// Bytes a used block spans once its alignment adjustment is included.
+fn used_size(adjustment: usize, size: usize) -> usize {
+  (adjustment + size).next_multiple_of(MIN_ALLOC_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn estimated_pool_holds_class_rounded_requests() {
+    for workload in [
+      &[(1030, 1)][..],
+      &[(5000, 8); 8],
+      &[(8, 4096), (3, 1), (70_000, 64)],
+      &[(9, 16), (4000, 2048), (1, 1), (9, 16)],
+    ] {
+      let size = SpeedAllocator::estimate_required_pool_size(workload, 0);
+      let mut allocator = SpeedAllocator::new(size);
+      for &(size, alignment) in workload {
+        assert!(allocator.allocate(size, alignment).is_some());
+      }
+    }
+    assert_eq!(SpeedAllocator::estimate_required_pool_size(&[], 50), MIN_ALLOC_SIZE);
+  }
+
+  #[test]
+  fn estimating_a_huge_workload_allocates_nothing() {
+    // 2^20 requests of 2^40 bytes, far more than any host holds
+    let workload = vec![(1 << 40, 8); 1 << 20];
+    assert_eq!(SpeedAllocator::estimate_required_pool_size(&workload, 0), 1 << 60);
+    let overflowing = [(usize::MAX / 2, 8), (usize::MAX / 2, 8), (usize::MAX / 2, 8)];
+    assert_eq!(SpeedAllocator::estimate_required_pool_size(&overflowing, 0), usize::MAX);
+  }
+
+  #[test]
+  fn empty_range_overlaps_nothing() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptr = allocator.allocate(64, 8).unwrap();
+    let offset = allocator.allocation_range(ptr).unwrap().0;
+    assert_eq!(allocator.allocations_overlapping(offset + 8, offset + 8), []);
+    assert_eq!(allocator.allocations_overlapping(offset + 16, offset + 8), []);
+    assert_eq!(
+      allocator.allocations_overlapping(offset + 8, offset + 9),
+      [(offset, 64)]
+    );
+  }
+
+  #[test]
+  fn empty_fill_pattern_is_rejected() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptr = allocator.allocate(64, 8).unwrap();
+    assert!(!allocator.fill_allocation_pattern_and_check(ptr, &[]));
+    assert_eq!(allocator.check_result(ptr, &[]), None);
+    assert!(allocator.fill_allocation_pattern_and_check(ptr, &[1, 2, 3]));
+    assert_eq!(allocator.check_result(ptr, &[1, 2, 3]), Some(0));
+    assert_eq!(allocator.check_result(ptr, &[3, 2, 1]), Some(43));
+  }
+
+  #[test]
+  fn misplaced_free_block_is_reported() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptr = allocator.allocate(64, 8).unwrap();
+    allocator.allocate(64, 8).unwrap();
+    allocator.deallocate(ptr);
+    assert_eq!(allocator.validate_all_free_blocks_in_correct_bins(), Ok(()));
+    let block = allocator.bins[binmap_down(64).flat_index()].unwrap();
+    allocator.blocks[block].size = 48;
+    assert!(matches!(
+      allocator.validate_all_free_blocks_in_correct_bins(),
+      Err(ValidationError::MisplacedFreeBlock { offset: 0, size: 48, .. })
+    ));
+  }
+
+  #[test]
+  fn free_list_cycle_is_reported() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptrs: Vec<_> = (0..4).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+    allocator.deallocate(ptrs[0]);
+    allocator.deallocate(ptrs[2]);
+    assert_eq!(allocator.verify_no_cycles_in_free_list(), Ok(()));
+    let idx = binmap_down(64).flat_index();
+    let head = allocator.bins[idx].unwrap();
+    allocator.blocks[head].next_free = Some(head);
+    assert_eq!(
+      allocator.verify_no_cycles_in_free_list(),
+      Err(ValidationError::FreeListCycle { bin: BinRef::from_index(idx) })
+    );
+  }
+
+  #[test]
+  fn quarantined_block_is_not_reused_until_it_leaves() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_quarantine_depth(2);
+    let ptrs: Vec<_> = (0..5).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+    allocator.deallocate(ptrs[0]);
+    allocator.deallocate(ptrs[2]);
+    let reused = allocator.allocate(64, 8).unwrap();
+    assert_ne!(reused, ptrs[0]);
+    assert_ne!(reused, ptrs[2]);
+    allocator.deallocate(ptrs[4]);
+    assert_eq!(allocator.quarantined_blocks(), 2);
+    assert_eq!(allocator.allocate(64, 8), Some(ptrs[0]));
+    assert_eq!(allocator.validate(), Ok(()));
+  }
+
+  #[test]
+  fn failed_burst_is_rolled_back_past_the_quarantine() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_quarantine_depth(4);
+    assert!(allocator.allocate_burst(100, 64, 8).is_empty());
+    assert_eq!(allocator.quarantined_blocks(), 0);
+    assert_eq!(allocator.stats().free_bytes, 4096);
+    assert!(allocator.allocate(4096, 8).is_some());
+  }
+
+  #[test]
+  fn free_block_counters_match_the_free_lists() {
+    let mut allocator = SpeedAllocator::new(1 << 20);
+    let sizes = [24, 300, 300, 1000, 1030, 4096, 5000, 70_000];
+    let ptrs: Vec<_> = sizes
+      .iter()
+      .cycle()
+      .take(64)
+      .map(|&size| allocator.allocate(size, 8).unwrap())
+      .collect();
+    for &ptr in ptrs.iter().step_by(3) {
+      allocator.deallocate(ptr);
+    }
+    let free: Vec<_> = allocator
+      .blocks()
+      .filter(|block| block.is_free)
+      .map(|block| block.size)
+      .collect();
+    assert_eq!(allocator.largest_free_block(), free.iter().copied().max().unwrap());
+    for bytes in [0, 8, 24, 25, 300, 1000, 1024, 1031, 5000, 70_000, 1 << 20] {
+      let expected = free.iter().filter(|&&size| size >= bytes).count();
+      assert_eq!(allocator.free_block_count_over(bytes), expected, "{bytes} bytes");
+    }
+    let id = allocator.largest_free_block_id().unwrap();
+    assert_eq!(allocator.blocks[id].size, allocator.largest_free_block());
+  }
+
+  #[test]
+  fn corrupted_index_entry_poisons_lookups_until_rebuilt() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptr = allocator.allocate(64, 8).unwrap();
+    let offset = allocator.allocation_range(ptr).unwrap().0;
+    allocator.corrupt_index_entry(ptr);
+    assert_eq!(allocator.get_block_info(ptr), None);
+    assert!(allocator.is_poisoned());
+    assert_eq!(allocator.try_deallocate(ptr), Err(AllocError::Poisoned));
+    assert_eq!(allocator.allocate(8, 8), None);
+    assert!(allocator.validate().is_err());
+
+    allocator.rebuild_index();
+    assert_eq!(allocator.validate(), Ok(()));
+    assert_eq!(allocator.get_block_info(ptr).map(|info| info.offset), Some(offset));
+    allocator.corrupt_index_entry(ptr);
+    assert_eq!(
+      allocator.try_deallocate(ptr),
+      Err(AllocError::IndexCorrupted { offset })
+    );
+    allocator.rebuild_index();
+    assert_eq!(allocator.try_deallocate(ptr), Ok(()));
+    assert_eq!(allocator.validate(), Ok(()));
+  }
+
+  #[test]
+  fn refreshed_handles_agree_with_index_lookups_after_moves() {
+    let mut allocator = SpeedAllocator::new(1 << 16);
+    let mut handles: Vec<_> = (0..60)
+      .map(|i| {
+        let ptr = allocator.allocate(8 + i * 24 % 400, 8 << (i % 3)).unwrap();
+        allocator.allocation_handle(ptr).unwrap()
+      })
+      .collect();
+    let freed: Vec<_> = (0..handles.len())
+      .filter(|i| i % 3 != 1)
+      .rev()
+      .map(|i| handles.remove(i))
+      .collect();
+    for handle in &freed {
+      allocator.deallocate(handle.ptr);
+    }
+    let plan = allocator.freeze().plan_compaction();
+    assert!(!plan.relocations().is_empty());
+    assert!(allocator.commit_plan(&plan).iter().all(Result::is_ok));
+
+    let mut moved = 0;
+    for handle in &mut handles {
+      let old = handle.ptr;
+      allocator.refresh(handle).unwrap();
+      moved += (handle.ptr != old) as usize;
+      assert_eq!(
+        allocator.resolve_handle(handle).ok(),
+        allocator.get_block_info(handle.ptr)
+      );
+    }
+    assert_eq!(moved, plan.relocations().len());
+    for mut handle in freed {
+      assert_eq!(allocator.refresh(&mut handle), Err(AllocError::UnknownAllocation));
+    }
+    assert_eq!(allocator.validate(), Ok(()));
+  }
+
+  #[test]
+  fn handles_of_another_allocator_are_rejected() {
+    let mut first = SpeedAllocator::new(4096);
+    let mut second = SpeedAllocator::new(4096);
+    let ptr = first.allocate(64, 8).unwrap();
+    second.allocate(64, 8).unwrap();
+    let mut handle = first.allocation_handle(ptr).unwrap();
+    assert!(first.resolve_handle(&handle).is_ok());
+    assert_eq!(second.resolve_handle(&handle), Err(AllocError::UnknownAllocation));
+    assert_eq!(second.refresh(&mut handle), Err(AllocError::UnknownAllocation));
+    assert_eq!(
+      first.clone().resolve_handle(&handle),
+      Err(AllocError::UnknownAllocation)
+    );
+  }
+
+  #[test]
+  fn alternating_megabytes_give_the_exact_fragmentation_ratio() {
+    const MIB: usize = 1 << 20;
+    let mut allocator = SpeedAllocator::new(8 * MIB);
+    assert_eq!(allocator.stats().external_fragmentation, 0.0);
+    let ptrs: Vec<_> = (0..8).map(|_| allocator.allocate(MIB, 8).unwrap()).collect();
+    assert_eq!(allocator.stats().free_bytes, 0);
+    assert_eq!(allocator.stats().external_fragmentation, 0.0);
+
+    for &ptr in ptrs.iter().step_by(2) {
+      allocator.deallocate(ptr);
+    }
+    let stats = allocator.stats();
+    assert_eq!((stats.free_bytes, stats.largest_free_block), (4 * MIB, MIB));
+    assert_eq!(stats.external_fragmentation, 0.75);
+    assert_eq!(allocator.free_block_count_over(MIB), 4);
+    assert_eq!(allocator.free_block_count_over(MIB / 2), 4);
+    assert_eq!(allocator.free_block_count_over(MIB + 1), 0);
+
+    allocator.deallocate(ptrs[1]);
+    let stats = allocator.stats();
+    assert_eq!(stats.largest_free_block, 3 * MIB);
+    assert_eq!(stats.external_fragmentation, 1.0 - 3.0 / 5.0);
+    assert_eq!(allocator.free_block_count_over(MIB + 1), 1);
+  }
+
+  #[test]
+  fn allocations_split_the_free_block_and_frees_coalesce_it_back() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptrs: Vec<_> = [100, 8, 1000]
+      .iter()
+      .map(|&size| allocator.allocate(size, 8).unwrap())
+      .collect();
+    let sizes: Vec<_> = allocator.blocks().map(|block| (block.size, block.is_free)).collect();
+    assert_eq!(sizes, [(104, false), (8, false), (1000, false), (2984, true)]);
+
+    allocator.deallocate(ptrs[0]);
+    allocator.deallocate(ptrs[2]);
+    assert_eq!(allocator.stats().free_block_count, 2);
+    allocator.deallocate(ptrs[1]);
+    let sizes: Vec<_> = allocator.blocks().map(|block| (block.size, block.is_free)).collect();
+    assert_eq!(sizes, [(4096, true)]);
+    allocator.validate().unwrap();
+  }
+
+  #[test]
+  fn will_split_predicts_a_new_block() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_minimum_split_size(64);
+    let mut predictions = Vec::new();
+    for (size, alignment) in [(100, 8), (2000, 8), (8192, 8), (64, 256), (0, 8)] {
+      // the last request leaves less than the split threshold of the largest free block
+      let size = if size == 0 {
+        allocator.largest_free_block() - 32
+      } else {
+        size
+      };
+      let predicted = allocator.will_split(size, alignment);
+      let before = allocator.blocks().count();
+      match allocator.allocate(size, alignment) {
+        Some(_) => assert_eq!(
+          predicted,
+          Some(allocator.blocks().count() > before),
+          "{size} at {alignment}"
+        ),
+        None => assert_eq!(predicted, None, "{size} at {alignment}"),
+      }
+      predictions.push(predicted);
+    }
+    assert!(predictions.contains(&Some(true)) && predictions.contains(&Some(false)));
+  }
+
+  #[test]
+  fn node_counts_and_bookkeeping_follow_the_live_blocks() {
+    const N: usize = 16;
+    let mut allocator = SpeedAllocator::new(1 << 16);
+    let empty = allocator.stats().bookkeeping_bytes;
+    let ptrs: Vec<_> = (0..N).map(|_| allocator.allocate(100, 8).unwrap()).collect();
+    let stats = allocator.stats();
+    assert_eq!(stats.live_nodes, N + 1);
+    assert_eq!(
+      stats.bookkeeping_bytes - empty,
+      N * (size_of::<Block>() + size_of::<usize>() + size_of::<BlockId>())
+    );
+
+    for &ptr in ptrs.iter().step_by(2) {
+      allocator.deallocate(ptr);
+    }
+    let stats = allocator.stats();
+    assert_eq!(stats.live_nodes, stats.live_allocations + stats.free_block_count);
+    assert_eq!(stats.live_nodes as u64, stats.node_allocations - stats.node_frees);
+    assert_eq!(
+      allocator.bookkeeping_bytes_per_live_allocation(),
+      stats.bookkeeping_bytes as f64 / (N / 2) as f64
+    );
+
+    allocator.reset();
+    let stats = allocator.stats();
+    assert_eq!(
+      (stats.live_nodes, stats.live_allocations, stats.free_block_count),
+      (1, 0, 1)
+    );
+    assert_eq!(stats.node_allocations - stats.node_frees, 1);
+  }
+
+  #[test]
+  fn single_block_pool_frees_its_only_allocation_without_merging_it_into_itself() {
+    let mut allocator = SpeedAllocator::new(64);
+    let ptr = allocator.allocate(64, 8).unwrap();
+    assert_eq!(allocator.blocks().count(), 1);
+    allocator.deallocate(ptr);
+    let blocks: Vec<_> = allocator
+      .blocks()
+      .map(|block| (block.offset, block.size, block.is_free))
+      .collect();
+    assert_eq!(blocks, [(0, 64, true)]);
+    assert_eq!(allocator.stats().free_bytes, 64);
+    allocator.validate().unwrap();
+    assert!(allocator.allocate(64, 8).is_some());
+  }
+
+  #[test]
+  fn allocate_or_evict_retries_until_enough_is_evicted() {
+    let mut allocator = SpeedAllocator::new(1024);
+    let mut cache: Vec<_> = (0..4).map(|_| allocator.allocate(256, 8).unwrap()).collect();
+    let evict = |allocator: &mut SpeedAllocator| cache.pop().map(|ptr| allocator.deallocate(ptr)).is_some();
+    assert!(allocator.allocate_or_evict(512, 8, evict, 4).is_some());
+    // the first eviction frees 256 bytes, the second one makes them 512
+    assert_eq!(cache.len(), 2);
+
+    let mut nothing_left = |_: &mut SpeedAllocator| false;
+    assert!(allocator.allocate_or_evict(512, 8, &mut nothing_left, 8).is_none());
+  }
+
+  #[test]
+  fn tight_fit_takes_a_smaller_block_than_good_fit() {
+    let chosen = |policy| {
+      let mut allocator = SpeedAllocator::new(8192);
+      allocator.set_fit_policy(policy);
+      let small = allocator.allocate(1032, 8).unwrap();
+      allocator.allocate(8, 8).unwrap();
+      let large = allocator.allocate(1048, 8).unwrap();
+      allocator.allocate(8, 8).unwrap();
+      // both land in the class above the request's, the larger one at the head of its free list
+      allocator.deallocate(small);
+      allocator.deallocate(large);
+      let ptr = allocator.allocate(1020, 8).unwrap();
+      allocator.get_block_info(ptr).unwrap().offset
+    };
+    // offset 0 is the 1032-byte block, 1040 the 1048-byte one
+    assert_eq!(chosen(FitPolicy::GoodFit), 1040);
+    assert_eq!(chosen(FitPolicy::TightFit), 0);
+  }
+
+  #[test]
+  fn allocate_after_is_contiguous_or_fails() {
+    let mut allocator = SpeedAllocator::new(512);
+    let prev = allocator.allocate(100, 8).unwrap();
+    let (prev_offset, prev_size) = allocator.allocation_range(prev).unwrap();
+    let next = allocator.allocate_after(prev, 64, 8).unwrap();
+    assert_eq!(allocator.allocation_range(next).unwrap().0, prev_offset + prev_size);
+    assert_eq!(next.as_ptr() as usize, prev.as_ptr() as usize + prev_size);
+
+    // the neighbour of `prev` is now used
+    assert!(allocator.allocate_after(prev, 8, 8).is_none());
+    let aligned = allocator.allocate_after(next, 64, 128).unwrap();
+    assert!((aligned.as_ptr() as usize).is_multiple_of(128));
+    // what is left behind `aligned` can't hold the request
+    assert!(allocator.allocate_after(aligned, 512, 8).is_none());
+    allocator.validate().unwrap();
+  }
+
+  #[test]
+  fn exhausted_budget_fails_cleanly_and_a_generous_one_succeeds() {
+    let mut allocator = SpeedAllocator::new(1 << 16);
+    allocator.set_fit_policy(FitPolicy::TightFit);
+    // a free list of blocks just too small for the request, all visited before the search moves on
+    let holes: Vec<_> = (0..TIGHT_FIT_SCAN)
+      .map(|_| {
+        let hole = allocator.allocate(1024, 8).unwrap();
+        allocator.allocate(8, 8).unwrap();
+        hole
+      })
+      .collect();
+    for hole in holes {
+      allocator.deallocate(hole);
+    }
+    let state = allocator.dump_state_to_vec();
+    let generation = allocator.generation();
+
+    for units in [0, 1, TIGHT_FIT_SCAN as u64] {
+      assert_eq!(
+        allocator.allocate_with_budget(1040, 8, OperationBudget::new(units)),
+        Err(AllocError::BudgetExceeded),
+        "{units} units"
+      );
+    }
+    assert_eq!(allocator.dump_state_to_vec(), state);
+    assert_eq!(allocator.generation(), generation);
+    allocator.validate().unwrap();
+
+    let allocation = allocator
+      .allocate_with_budget(1040, 8, OperationBudget::new(64))
+      .unwrap();
+    assert!(allocation.size >= 1040);
+    assert!(allocator.resolve_handle(&allocation).is_ok());
   }
 }
@@ -0,0 +1,110 @@
+use std::ptr::NonNull;
+
+use crate::error::AllocError;
+use crate::pool::Pool;
+
+/// Pool cut into equal slots handed out from a free stack, for workloads where every allocation has the same
+/// size. Allocating and freeing are O(1) and never fragment the pool.
+pub struct FixedSizeArena {
+  pool: Pool,
+  slot_size: usize,
+  // offsets of the free slots, the next one to hand out last
+  free: Vec<usize>,
+  in_use: Vec<bool>,
+}
+
+impl FixedSizeArena {
+  /// Creates an arena over a fresh pool of `pool_size` bytes, split into as many `slot_size`-byte slots as fit.
+  /// Slots are handed out lowest offset first.
+  ///
+  /// # Panics
+  ///
+  /// If `slot_size` is zero.
+  pub fn new(pool_size: usize, slot_size: usize) -> Self {
+    assert!(slot_size > 0, "slot size must be non-zero");
+    let slots = pool_size / slot_size;
+    FixedSizeArena {
+      pool: Pool::new(pool_size),
+      slot_size,
+      free: (0..slots).rev().map(|slot| slot * slot_size).collect(),
+      in_use: vec![false; slots],
+    }
+  }
+
+  /// Offset of a free slot, or `None` once every slot is taken.
+  pub fn alloc(&mut self) -> Option<usize> {
+    let offset = self.free.pop()?;
+    self.in_use[offset / self.slot_size] = true;
+    Some(offset)
+  }
+
+  /// Returns the slot at `offset` to the free stack. Fails with [`AllocError::UnknownAllocation`] if `offset`
+  /// isn't the start of a slot currently handed out.
+  pub fn free(&mut self, offset: usize) -> Result<(), AllocError> {
+    if !offset.is_multiple_of(self.slot_size) {
+      return Err(AllocError::UnknownAllocation);
+    }
+    match self.in_use.get_mut(offset / self.slot_size) {
+      Some(in_use) if *in_use => {
+        *in_use = false;
+        self.free.push(offset);
+        Ok(())
+      }
+      _ => Err(AllocError::UnknownAllocation),
+    }
+  }
+
+  /// Host pointer to the slot at `offset`.
+  ///
+  /// # Panics
+  ///
+  /// If `offset` is outside the pool.
+  pub fn ptr(&self, offset: usize) -> NonNull<u8> {
+    self.pool.ptr_at(offset)
+  }
+
+  pub fn slot_size(&self) -> usize {
+    self.slot_size
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.in_use.len()
+  }
+
+  pub fn free_slots(&self) -> usize {
+    self.free.len()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn slots_are_handed_out_lowest_first_and_reused_last_in_first_out() {
+    let mut arena = FixedSizeArena::new(100, 24);
+    assert_eq!((arena.capacity(), arena.slot_size()), (4, 24));
+    let slots: Vec<_> = std::iter::from_fn(|| arena.alloc()).collect();
+    assert_eq!(slots, [0, 24, 48, 72]);
+    assert_eq!(arena.free_slots(), 0);
+
+    arena.free(24).unwrap();
+    arena.free(72).unwrap();
+    assert_eq!(arena.free_slots(), 2);
+    assert_eq!(arena.alloc(), Some(72));
+    assert_eq!(arena.alloc(), Some(24));
+    assert_eq!(arena.ptr(24).as_ptr() as usize - arena.ptr(0).as_ptr() as usize, 24);
+  }
+
+  #[test]
+  fn bad_frees_are_rejected() {
+    let mut arena = FixedSizeArena::new(96, 24);
+    let slot = arena.alloc().unwrap();
+    for offset in [slot + 8, 24, 96, usize::MAX] {
+      assert_eq!(arena.free(offset), Err(AllocError::UnknownAllocation), "{offset}");
+    }
+    arena.free(slot).unwrap();
+    assert_eq!(arena.free(slot), Err(AllocError::UnknownAllocation));
+    assert_eq!(arena.free_slots(), 4);
+  }
+}
@@ -0,0 +1,225 @@
+use std::path::PathBuf;
+use std::{fs, io};
+
+use crate::allocator::SpeedAllocator;
+
+const BENCHMARK_ALLOCATIONS: usize = 10_000;
+const BENCHMARK_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Request sizes fed to [`SpeedAllocator::run_fragmentation_benchmark`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum AllocationPattern {
+  Uniform(usize),
+  /// `small_fraction` of the requests (between `0.0` and `1.0`) are `small`, the rest are `large`.
+  Bimodal(usize, usize, f64),
+  /// A text file with one request size in bytes per line, replayed in a loop.
+  Real(PathBuf),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BenchmarkResult {
+  pub min_frag: f64,
+  pub max_frag: f64,
+  pub avg_frag: f64,
+  pub final_frag: f64,
+  pub total_failures: usize,
+}
+
+/// Outcome of [`SpeedAllocator::stress_test_integrity`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StressTestResult {
+  pub total_operations: usize,
+  pub integrity_checks: usize,
+  /// One message per failed check, naming the operation it followed.
+  pub failures: Vec<String>,
+}
+
+// xorshift64, deterministic across platforms so runs can be compared.
+pub(crate) struct XorShift(u64);
+
+impl XorShift {
+  pub(crate) fn new(seed: u64) -> Self {
+    XorShift(seed.max(1))
+  }
+
+  pub(crate) fn next_u64(&mut self) -> u64 {
+    self.0 ^= self.0 << 13;
+    self.0 ^= self.0 >> 7;
+    self.0 ^= self.0 << 17;
+    self.0
+  }
+
+  pub(crate) fn below(&mut self, bound: usize) -> usize {
+    (self.next_u64() % bound as u64) as usize
+  }
+
+  pub(crate) fn unit(&mut self) -> f64 {
+    (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+  }
+}
+
+impl SpeedAllocator {
+  /// Replays 10 000 allocations drawn from `pattern`, each followed by a coin-flip free of a random live
+  /// allocation, on a scratch allocator with this allocator's pool size, and samples
+  /// [`Stats::external_fragmentation`](crate::Stats::external_fragmentation) after every step.
+  /// Only reading the trace of [`AllocationPattern::Real`] can fail.
+  pub fn run_fragmentation_benchmark(&self, pattern: AllocationPattern) -> io::Result<BenchmarkResult> {
+    let trace = match &pattern {
+      AllocationPattern::Real(path) => parse_trace(&fs::read_to_string(path)?)?,
+      _ => Vec::new(),
+    };
+    let mut scratch = SpeedAllocator::new(self.stats().pool_size);
+    let mut rng = XorShift::new(BENCHMARK_SEED);
+    let mut live = Vec::new();
+    let mut result =
+      BenchmarkResult { min_frag: f64::MAX, max_frag: 0.0, avg_frag: 0.0, final_frag: 0.0, total_failures: 0 };
+    for step in 0..BENCHMARK_ALLOCATIONS {
+      let size = match &pattern {
+        AllocationPattern::Uniform(size) => *size,
+        AllocationPattern::Bimodal(small, large, small_fraction) => {
+          if rng.unit() < *small_fraction {
+            *small
+          } else {
+            *large
+          }
+        }
+        AllocationPattern::Real(_) if trace.is_empty() => 0,
+        AllocationPattern::Real(_) => trace[step % trace.len()],
+      };
+      match scratch.allocate(size, 8) {
+        Some(ptr) => live.push(ptr),
+        None => result.total_failures += 1,
+      }
+      if !live.is_empty() && rng.next_u64() & 1 == 0 {
+        let victim = rng.below(live.len());
+        scratch.deallocate(live.swap_remove(victim));
+      }
+      let frag = scratch.stats().external_fragmentation;
+      result.min_frag = result.min_frag.min(frag);
+      result.max_frag = result.max_frag.max(frag);
+      result.avg_frag += frag / BENCHMARK_ALLOCATIONS as f64;
+      result.final_frag = frag;
+    }
+    Ok(result)
+  }
+}
+
+impl SpeedAllocator {
+  /// Runs `iterations` seeded random allocations and frees against this allocator, calling
+  /// [`validate`](Self::validate) after every 10 operations and once at the end. Everything the test allocated is
+  /// freed before returning, so without a [quarantine](Self::set_quarantine_depth) the layout is left as it was
+  /// and the same seed always replays the same sequence. With one, the blocks freed last may still be held in it.
+  pub fn stress_test_integrity(&mut self, iterations: usize, seed: u64) -> StressTestResult {
+    let mut rng = XorShift::new(seed);
+    let mut live = Vec::new();
+    let mut result = StressTestResult::default();
+    for op in 1..=iterations {
+      if live.is_empty() || rng.below(3) != 0 {
+        let size = rng.below(4096);
+        let alignment = 1 << rng.below(8);
+        if let Some(ptr) = self.allocate(size, alignment) {
+          live.push(ptr);
+        }
+      } else {
+        let victim = rng.below(live.len());
+        self.deallocate(live.swap_remove(victim));
+      }
+      result.total_operations += 1;
+      if op % 10 == 0 {
+        self.record_integrity_check(&mut result, op);
+      }
+    }
+    for ptr in live {
+      self.take_back(ptr);
+    }
+    self.record_integrity_check(&mut result, iterations);
+    result
+  }
+
+  fn record_integrity_check(&self, result: &mut StressTestResult, op: usize) {
+    result.integrity_checks += 1;
+    if let Err(error) = self.validate() {
+      result.failures.push(format!("after operation {op}: {error}"));
+    }
+  }
+}
+
+fn parse_trace(text: &str) -> io::Result<Vec<usize>> {
+  text
+    .lines()
+    .map(str::trim)
+    .filter(|line| !line.is_empty())
+    .map(|line| {
+      line
+        .parse()
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{line:?}: {err}")))
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn benchmark_is_deterministic_and_bounded() {
+    let allocator = SpeedAllocator::new(1 << 20);
+    let result = allocator
+      .run_fragmentation_benchmark(AllocationPattern::Bimodal(64, 4096, 0.9))
+      .unwrap();
+    assert_eq!(
+      allocator
+        .run_fragmentation_benchmark(AllocationPattern::Bimodal(64, 4096, 0.9))
+        .unwrap(),
+      result
+    );
+    assert!(0.0 <= result.min_frag && result.min_frag <= result.avg_frag && result.avg_frag <= result.max_frag);
+    assert!(result.max_frag < 1.0);
+
+    // uniform requests on a pool big enough for all of them never fail
+    let uniform = allocator
+      .run_fragmentation_benchmark(AllocationPattern::Uniform(64))
+      .unwrap();
+    assert_eq!(uniform.total_failures, 0);
+  }
+
+  #[test]
+  fn real_traces_are_replayed_and_bad_ones_rejected() {
+    let dir = std::env::temp_dir().join(format!("speed-allocator-trace-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let (good, bad) = (dir.join("good.txt"), dir.join("bad.txt"));
+    fs::write(&good, "64\n\n  128 \n1000000\n").unwrap();
+    fs::write(&bad, "64\nlots\n").unwrap();
+
+    let allocator = SpeedAllocator::new(1 << 16);
+    let result = allocator
+      .run_fragmentation_benchmark(AllocationPattern::Real(good))
+      .unwrap();
+    // every fourth request is larger than the pool
+    assert!(result.total_failures >= BENCHMARK_ALLOCATIONS / 4);
+    let error = allocator
+      .run_fragmentation_benchmark(AllocationPattern::Real(bad))
+      .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    let missing = allocator.run_fragmentation_benchmark(AllocationPattern::Real(dir.join("missing.txt")));
+    assert_eq!(missing.unwrap_err().kind(), io::ErrorKind::NotFound);
+    fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn stress_test_validates_and_leaves_the_layout_as_it_found_it() {
+    let mut allocator = SpeedAllocator::new(1 << 18);
+    let kept = allocator.allocate(1000, 8).unwrap();
+    let before = allocator.dump_state_to_vec();
+    let result = allocator.stress_test_integrity(1005, 42);
+    assert_eq!(result.total_operations, 1005);
+    assert_eq!(result.integrity_checks, 101);
+    assert_eq!(result.failures, Vec::<String>::new());
+    assert_eq!(allocator.dump_state_to_vec(), before);
+    assert!(allocator.get_block_info(kept).is_some());
+
+    allocator.set_quarantine_depth(4);
+    assert!(allocator.stress_test_integrity(1000, 42).failures.is_empty());
+    assert!(allocator.quarantined_blocks() <= 4);
+  }
+}
@@ -0,0 +1,109 @@
+use crate::mapping::{BIN_COUNT, SUB_BIN};
+
+// Occupancy bits of the bins or of one bin's sub-bins. Indices are masked to the word size before shifting, so
+// a bad index trips the debug assertion instead of an overflowing shift.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Bitmap(u64);
+
+const _: () = assert!(BIN_COUNT <= Bitmap::BITS && SUB_BIN <= Bitmap::BITS);
+
+impl Bitmap {
+  pub const BITS: usize = u64::BITS as usize;
+
+  pub fn from_bits(bits: u64) -> Self {
+    Bitmap(bits)
+  }
+
+  pub fn bits(&self) -> u64 {
+    self.0
+  }
+
+  pub fn set(&mut self, i: usize) {
+    self.0 |= Self::bit(i);
+  }
+
+  pub fn clear(&mut self, i: usize) {
+    self.0 &= !Self::bit(i);
+  }
+
+  pub fn test(&self, i: usize) -> bool {
+    self.0 & Self::bit(i) != 0
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.0 == 0
+  }
+
+  // lowest set bit at or above `i`
+  pub fn first_set_from(&self, i: usize) -> Option<usize> {
+    if i >= Self::BITS {
+      return None;
+    }
+    let bits = self.0 & (!0 << i);
+    (bits != 0).then(|| bits.trailing_zeros() as usize)
+  }
+
+  pub fn last_set(&self) -> Option<usize> {
+    (self.0 != 0).then(|| Self::BITS - 1 - self.0.leading_zeros() as usize)
+  }
+
+  // number of bits set in only one of the two maps
+  pub fn count_differing(&self, other: &Bitmap) -> usize {
+    (self.0 ^ other.0).count_ones() as usize
+  }
+
+  fn bit(i: usize) -> u64 {
+    debug_assert!(i < Self::BITS, "bit {i} is out of range");
+    1 << (i & (Self::BITS - 1))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn boundary_bits_are_set_tested_and_cleared() {
+    let mut map = Bitmap::default();
+    for i in [0, 1, Bitmap::BITS - 2, Bitmap::BITS - 1] {
+      map.set(i);
+      assert!(map.test(i));
+    }
+    assert_eq!(map.bits(), 0b11 | 0b11 << (Bitmap::BITS - 2));
+    assert_eq!(map.last_set(), Some(Bitmap::BITS - 1));
+    map.clear(Bitmap::BITS - 1);
+    map.clear(0);
+    assert!(!map.test(Bitmap::BITS - 1) && !map.test(0));
+    assert_eq!(map.last_set(), Some(Bitmap::BITS - 2));
+    map.clear(1);
+    map.clear(Bitmap::BITS - 2);
+    assert!(map.is_empty());
+    assert_eq!(map.last_set(), None);
+  }
+
+  #[test]
+  fn first_set_from_handles_every_start() {
+    let map = Bitmap::from_bits(1 | 1 << 40 | 1 << (Bitmap::BITS - 1));
+    assert_eq!(map.first_set_from(0), Some(0));
+    assert_eq!(map.first_set_from(1), Some(40));
+    assert_eq!(map.first_set_from(41), Some(Bitmap::BITS - 1));
+    assert_eq!(map.first_set_from(Bitmap::BITS - 1), Some(Bitmap::BITS - 1));
+    assert_eq!(map.first_set_from(Bitmap::BITS), None);
+    assert_eq!(map.first_set_from(usize::MAX), None);
+    assert_eq!(Bitmap::default().first_set_from(0), None);
+  }
+
+  #[test]
+  fn differing_bits_are_counted() {
+    let a = Bitmap::from_bits(0b1011);
+    assert_eq!(a.count_differing(&Bitmap::from_bits(0b0110)), 3);
+    assert_eq!(a.count_differing(&a), 0);
+  }
+
+  #[test]
+  #[cfg(debug_assertions)]
+  #[should_panic(expected = "out of range")]
+  fn out_of_range_bit_trips_the_debug_assertion() {
+    Bitmap::default().set(Bitmap::BITS);
+  }
+}
@@ -1,6 +1,278 @@
-pub struct BlockHeader {
+#[cfg(feature = "backtrace")]
+use std::backtrace::Backtrace;
+use std::ops::{Index, IndexMut};
+#[cfg(feature = "backtrace")]
+use std::sync::Arc;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BlockId(usize);
+
+#[derive(Clone)]
+pub struct Block {
+  pub offset: usize,
   pub size: usize,
-  pub free: bool,
-  pub next_free: Option<Box<BlockHeader>>,
-  pub prev_free: Option<Box<BlockHeader>>,
+  // bytes skipped at the start of a used block to satisfy its alignment
+  pub adjustment: usize,
+  pub is_free: bool,
+  // taken out of the free lists by `drain_free_blocks`, never handed out again
+  pub drained: bool,
+  // starts at a boundary cut by `split_at_power_of_two_boundaries`, never merged into the block before it
+  pub fenced: bool,
+  // freed but held back from the free lists by the quarantine, counts as used meanwhile
+  pub quarantined: bool,
+  // used block whose allocation `commit_plan` must not move, set by `pin`
+  pub pinned: bool,
+  // id of the allocation occupying a used block
+  pub alloc_id: u64,
+  // tag given to `allocate_tagged` for a used block
+  #[cfg(feature = "tagging")]
+  pub tag: Option<u64>,
+  // where the allocation occupying a used block was made
+  #[cfg(feature = "backtrace")]
+  pub backtrace: Option<Arc<Backtrace>>,
+  pub prev_physical: Option<BlockId>,
+  pub next_physical: Option<BlockId>,
+  pub prev_free: Option<BlockId>,
+  pub next_free: Option<BlockId>,
+}
+
+impl Block {
+  // Free block with no neighbours yet.
+  pub fn new(offset: usize, size: usize) -> Block {
+    Block {
+      offset,
+      size,
+      adjustment: 0,
+      is_free: true,
+      drained: false,
+      fenced: false,
+      quarantined: false,
+      pinned: false,
+      alloc_id: 0,
+      #[cfg(feature = "tagging")]
+      tag: None,
+      #[cfg(feature = "backtrace")]
+      backtrace: None,
+      prev_physical: None,
+      next_physical: None,
+      prev_free: None,
+      next_free: None,
+    }
+  }
+
+  // Cuts the block after its first `at` bytes and returns the rest. Every field is named below so that adding one
+  // doesn't compile until its rule is picked here and in `absorb`:
+  // - offset, size: divided at `at`
+  // - is_free, drained, quarantined: copied, only free blocks are split
+  // - adjustment, alloc_id, pinned, tag, backtrace: cleared, they describe an allocation, which stays in the
+  //   first part
+  // - fenced: cleared, the caller fences the rest when the cut has to survive later frees
+  // - links: left unset, the caller owns the ids and relinks both parts
+  pub fn split_off(&mut self, at: usize) -> Block {
+    debug_assert!(
+      at > 0 && at < self.size,
+      "split at {at} outside a block of {} bytes",
+      self.size
+    );
+    let Block {
+      offset,
+      size,
+      adjustment: _,
+      is_free,
+      drained,
+      fenced: _,
+      quarantined,
+      pinned: _,
+      alloc_id: _,
+      #[cfg(feature = "tagging")]
+        tag: _,
+      #[cfg(feature = "backtrace")]
+        backtrace: _,
+      prev_physical: _,
+      next_physical: _,
+      prev_free: _,
+      next_free: _,
+    } = *self;
+    self.size = at;
+    Block { is_free, drained, quarantined, ..Block::new(offset + at, size - at) }
+  }
+
+  // Grows the block over `other`, its next physical neighbour, with the same field-by-field rules as `split_off`:
+  // - offset, size: `other` must start where the block ends, sizes add up
+  // - is_free, drained, fenced, quarantined: only unfenced free blocks are merged, nothing to combine
+  // - adjustment, alloc_id, pinned, tag, backtrace: dropped, a free block carries no allocation
+  // - next_physical: taken over, the other links die with `other`'s node
+  pub fn absorb(&mut self, other: Block) {
+    let Block {
+      offset,
+      size,
+      adjustment: _,
+      is_free,
+      drained,
+      fenced,
+      quarantined,
+      pinned: _,
+      alloc_id: _,
+      #[cfg(feature = "tagging")]
+        tag: _,
+      #[cfg(feature = "backtrace")]
+        backtrace: _,
+      prev_physical: _,
+      next_physical,
+      prev_free: _,
+      next_free: _,
+    } = other;
+    debug_assert_eq!(
+      offset,
+      self.offset + self.size,
+      "absorbed block isn't the next neighbour"
+    );
+    debug_assert!(
+      self.is_free && is_free && !drained && !fenced && !quarantined,
+      "only unfenced free blocks merge"
+    );
+    self.size += size;
+    self.next_physical = next_physical;
+  }
+
+  // tag of a used block, 0 when it has none or tagging is off
+  pub fn tag(&self) -> u64 {
+    #[cfg(feature = "tagging")]
+    return self.tag.unwrap_or(0);
+    #[cfg(not(feature = "tagging"))]
+    0
+  }
+}
+
+// Slab owning every block node, released slots are reused by later splits.
+#[derive(Clone, Default)]
+pub struct Blocks {
+  slots: Vec<Block>,
+  vacant: Vec<BlockId>,
+}
+
+impl Blocks {
+  pub fn insert(&mut self, block: Block) -> BlockId {
+    match self.vacant.pop() {
+      Some(id) => {
+        self.slots[id.0] = block;
+        id
+      }
+      None => {
+        self.slots.push(block);
+        BlockId(self.slots.len() - 1)
+      }
+    }
+  }
+
+  // Frees the slot of `id` and hands back the block it held.
+  pub fn release(&mut self, id: BlockId) -> Block {
+    self.vacant.push(id);
+    std::mem::replace(&mut self.slots[id.0], Block::new(0, 0))
+  }
+
+  pub fn get(&self, id: BlockId) -> Option<&Block> {
+    self.slots.get(id.0)
+  }
+
+  pub fn slot_count(&self) -> usize {
+    self.slots.len()
+  }
+}
+
+impl Index<BlockId> for Blocks {
+  type Output = Block;
+
+  fn index(&self, id: BlockId) -> &Block {
+    &self.slots[id.0]
+  }
+}
+
+impl IndexMut<BlockId> for Blocks {
+  fn index_mut(&mut self, id: BlockId) -> &mut Block {
+    &mut self.slots[id.0]
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Block with every field away from its `Block::new` value, as if it had held an allocation.
+  fn dirty(offset: usize, size: usize) -> Block {
+    Block {
+      adjustment: 8,
+      is_free: true,
+      drained: true,
+      fenced: true,
+      quarantined: true,
+      pinned: true,
+      alloc_id: 7,
+      #[cfg(feature = "tagging")]
+      tag: Some(3),
+      #[cfg(feature = "backtrace")]
+      backtrace: Some(Arc::new(Backtrace::disabled())),
+      prev_physical: Some(BlockId(1)),
+      next_physical: Some(BlockId(2)),
+      prev_free: Some(BlockId(3)),
+      next_free: Some(BlockId(4)),
+      ..Block::new(offset, size)
+    }
+  }
+
+  #[test]
+  fn split_off_follows_the_rule_of_every_field() {
+    let mut block = dirty(64, 256);
+    let rest = block.split_off(96);
+    assert_eq!((block.offset, block.size), (64, 96));
+    assert_eq!((rest.offset, rest.size), (160, 160));
+    assert!(rest.is_free && rest.drained && rest.quarantined);
+    assert!(!rest.fenced);
+    assert_eq!((rest.adjustment, rest.alloc_id, rest.pinned), (0, 0, false));
+    assert_eq!(rest.tag(), 0);
+    #[cfg(feature = "backtrace")]
+    assert!(rest.backtrace.is_none());
+    assert_eq!(
+      (rest.prev_physical, rest.next_physical, rest.prev_free, rest.next_free),
+      (None, None, None, None)
+    );
+    // the first part keeps everything but its size
+    assert!(block.is_free && block.drained && block.fenced && block.quarantined && block.pinned);
+    assert_eq!((block.adjustment, block.alloc_id), (8, 7));
+    assert_eq!(block.next_physical, Some(BlockId(2)));
+  }
+
+  #[test]
+  fn absorb_follows_the_rule_of_every_field() {
+    let mut block = Block { prev_physical: Some(BlockId(1)), prev_free: Some(BlockId(3)), ..Block::new(0, 64) };
+    let other = Block { drained: false, fenced: false, quarantined: false, ..dirty(64, 32) };
+    block.absorb(other);
+    assert_eq!((block.offset, block.size), (0, 96));
+    assert!(block.is_free && !block.drained && !block.fenced && !block.quarantined && !block.pinned);
+    assert_eq!((block.adjustment, block.alloc_id, block.tag()), (0, 0, 0));
+    assert_eq!(block.next_physical, Some(BlockId(2)));
+    assert_eq!(
+      (block.prev_physical, block.prev_free, block.next_free),
+      (Some(BlockId(1)), Some(BlockId(3)), None)
+    );
+  }
+
+  #[test]
+  #[cfg(debug_assertions)]
+  #[should_panic(expected = "isn't the next neighbour")]
+  fn absorb_rejects_a_block_that_does_not_follow() {
+    Block::new(0, 64).absorb(Block::new(128, 64));
+  }
+
+  #[test]
+  fn released_slots_are_reused() {
+    let mut blocks = Blocks::default();
+    let first = blocks.insert(Block::new(0, 64));
+    let second = blocks.insert(Block::new(64, 64));
+    assert_eq!(blocks.release(first).offset, 0);
+    assert_eq!(blocks.insert(Block::new(128, 64)), first);
+    assert_eq!((blocks[first].offset, blocks[second].offset), (128, 64));
+    assert_eq!(blocks.slot_count(), 2);
+    assert!(blocks.get(BlockId(2)).is_none());
+  }
 }
@@ -0,0 +1,54 @@
+/// Cap on the work of one [`SpeedAllocator::allocate_with_budget`] call, counted in abstract units rather than
+/// time so the outcome is the same on every run: one unit per bitmap scanned, per free-list node visited and per
+/// block node created by a split.
+///
+/// [`SpeedAllocator::allocate_with_budget`]: crate::SpeedAllocator::allocate_with_budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OperationBudget {
+  units: u64,
+  exhausted: bool,
+}
+
+impl OperationBudget {
+  pub fn new(units: u64) -> OperationBudget {
+    OperationBudget { units, exhausted: false }
+  }
+
+  /// Units left.
+  pub fn units(&self) -> u64 {
+    self.units
+  }
+
+  // Takes one unit, or flags the budget as exhausted and returns `None` so searches can bail out with `?`.
+  pub(crate) fn spend(&mut self) -> Option<()> {
+    match self.units.checked_sub(1) {
+      Some(units) => {
+        self.units = units;
+        Some(())
+      }
+      None => {
+        self.exhausted = true;
+        None
+      }
+    }
+  }
+
+  pub(crate) fn is_exhausted(&self) -> bool {
+    self.exhausted
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn spending_past_the_last_unit_exhausts_the_budget() {
+    let mut budget = OperationBudget::new(2);
+    assert_eq!((budget.spend(), budget.spend()), (Some(()), Some(())));
+    assert!(!budget.is_exhausted());
+    assert_eq!(budget.spend(), None);
+    assert!(budget.is_exhausted());
+    assert_eq!(budget.units(), 0);
+  }
+}
@@ -0,0 +1,168 @@
+use std::collections::BTreeMap;
+
+use crate::allocator::Stats;
+use crate::block::BlockId;
+use crate::error::AllocError;
+use crate::facade::{next_allocator_id, Allocation, MemoryAllocator, Request, RequestFlags};
+use crate::pool::Pool;
+use crate::validate::ValidationError;
+
+/// Allocator that only moves a cursor up the pool, for tools and tests that don't care about reuse. Frees
+/// are checked but give no memory back until every allocation is freed, which rewinds the cursor to the
+/// start of the pool. Supports [`RequestFlags::ZEROED`] only.
+pub struct BumpAllocator {
+  pool: Pool,
+  // offset of the first byte never handed out since the last rewind
+  top: usize,
+  // offset -> allocation id of every live allocation
+  live: BTreeMap<usize, u64>,
+  next_alloc_id: u64,
+  generation: u64,
+  id: u64,
+}
+
+impl BumpAllocator {
+  /// Creates an allocator over a fresh pool of `size` bytes.
+  pub fn new(size: usize) -> Self {
+    BumpAllocator {
+      pool: Pool::new(size),
+      top: 0,
+      live: BTreeMap::new(),
+      next_alloc_id: 1,
+      generation: 0,
+      id: next_allocator_id(),
+    }
+  }
+}
+
+impl MemoryAllocator for BumpAllocator {
+  fn allocate(&mut self, request: Request) -> Result<Allocation, AllocError> {
+    let Request { size, alignment, flags, tag } = request;
+    if !flags.difference(RequestFlags::ZEROED).is_empty() {
+      return Err(AllocError::Unsupported { what: "request flag" });
+    }
+    if tag.is_some() {
+      return Err(AllocError::Unsupported { what: "tags" });
+    }
+    if !alignment.is_power_of_two() {
+      return Err(AllocError::InvalidAlignment { alignment });
+    }
+    let out_of_memory = AllocError::OutOfMemory { size, alignment };
+    let address = self.pool.address();
+    let start = (address + self.top)
+      .checked_next_multiple_of(alignment)
+      .ok_or(out_of_memory)?
+      - address;
+    let end = start.checked_add(size.max(1)).ok_or(out_of_memory)?;
+    if end > self.pool.len() {
+      return Err(out_of_memory);
+    }
+    if flags.contains(RequestFlags::ZEROED) {
+      self.pool.fill(start, size, 0);
+    }
+    let alloc_id = self.next_alloc_id;
+    self.next_alloc_id += 1;
+    self.top = end;
+    self.live.insert(start, alloc_id);
+    self.generation += 1;
+    Ok(Allocation {
+      ptr: self.pool.ptr_at(start),
+      size,
+      epoch: 0,
+      allocator_id: self.id,
+      block: BlockId::default(),
+      alloc_id,
+    })
+  }
+
+  fn deallocate(&mut self, allocation: Allocation) -> Result<(), AllocError> {
+    let offset = self
+      .pool
+      .offset_of(allocation.ptr)
+      .ok_or(AllocError::UnknownAllocation)?;
+    if allocation.allocator_id != self.id || self.live.get(&offset) != Some(&allocation.alloc_id) {
+      return Err(AllocError::UnknownAllocation);
+    }
+    self.live.remove(&offset);
+    if self.live.is_empty() {
+      self.top = 0;
+    }
+    self.generation += 1;
+    Ok(())
+  }
+
+  fn stats(&self) -> Stats {
+    let free_bytes = self.pool.len() - self.top;
+    Stats {
+      pool_size: self.pool.len(),
+      used_bytes: self.top,
+      free_bytes,
+      free_block_count: (free_bytes > 0) as usize,
+      largest_free_block: free_bytes,
+      external_fragmentation: 0.0,
+      live_allocations: self.live.len(),
+      live_nodes: 0,
+      bookkeeping_bytes: self.live.len() * 2 * size_of::<u64>(),
+      node_allocations: 0,
+      node_frees: 0,
+      generation: self.generation,
+      exact_fit_hits: 0,
+      rounded_fit_hits: 0,
+    }
+  }
+
+  fn validate(&self) -> Result<(), ValidationError> {
+    match self.live.last_key_value() {
+      Some((&offset, _)) if offset >= self.top => Err(ValidationError::StaleIndexEntry { offset }),
+      _ => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn frees_only_rewind_once_everything_is_freed() {
+    let mut bump = BumpAllocator::new(4096);
+    let first = bump.allocate(Request::new(100, 8)).unwrap();
+    let second = bump.allocate(Request::new(10, 64)).unwrap();
+    assert_eq!(second.ptr.as_ptr() as usize % 64, 0);
+    let top = bump.stats().used_bytes;
+
+    bump.deallocate(first).unwrap();
+    let third = bump.allocate(Request::new(8, 8)).unwrap();
+    assert!(third.ptr > second.ptr);
+    assert!(bump.stats().used_bytes > top);
+    assert_eq!(bump.stats().live_allocations, 2);
+
+    bump.deallocate(second).unwrap();
+    bump.deallocate(third).unwrap();
+    let stats = bump.stats();
+    assert_eq!((stats.used_bytes, stats.free_bytes), (0, 4096));
+    assert_eq!(bump.allocate(Request::new(100, 8)).unwrap().ptr, first.ptr);
+  }
+
+  #[test]
+  fn requests_past_the_pool_end_fail() {
+    let mut bump = BumpAllocator::new(4096);
+    bump.allocate(Request::new(4000, 8)).unwrap();
+    assert_eq!(
+      bump.allocate(Request::new(128, 8)),
+      Err(AllocError::OutOfMemory { size: 128, alignment: 8 })
+    );
+    assert_eq!(
+      bump.allocate(Request::new(usize::MAX, 8)),
+      Err(AllocError::OutOfMemory { size: usize::MAX, alignment: 8 })
+    );
+    assert_eq!(
+      bump.allocate(Request::new(8, 24)),
+      Err(AllocError::InvalidAlignment { alignment: 24 })
+    );
+    let rest = bump.stats().free_bytes;
+    bump.allocate(Request::new(rest, 1)).unwrap();
+    assert_eq!(bump.stats().largest_free_block, 0);
+    assert_eq!(bump.validate(), Ok(()));
+  }
+}
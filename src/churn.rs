@@ -0,0 +1,55 @@
+use crate::allocator::SpeedAllocator;
+
+/// Free list traffic of one size class, see [`SpeedAllocator::monitor_bin_churn`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BinChurnReport {
+  pub insertions: u64,
+  pub removals: u64,
+  /// `(insertions + removals) / 2 / max(insertions, removals)`, or `0.0` before any traffic. Close to `1.0`
+  /// when blocks enter and leave the class at the same rate.
+  pub churn_ratio: f64,
+}
+
+impl SpeedAllocator {
+  /// How often blocks entered and left the free list of the size class at `bin_idx`, a flat index as returned by
+  /// [`BinRef::flat_index`](crate::BinRef::flat_index). A class with far more traffic than its neighbours is
+  /// the one the workload keeps splitting and merging through. Out-of-range indices report no traffic.
+  pub fn monitor_bin_churn(&self, bin_idx: usize) -> BinChurnReport {
+    let insertions = self.bin_insertions.get(bin_idx).copied().unwrap_or(0);
+    let removals = self.bin_removals.get(bin_idx).copied().unwrap_or(0);
+    let busiest = insertions.max(removals);
+    BinChurnReport {
+      insertions,
+      removals,
+      churn_ratio: if busiest == 0 {
+        0.0
+      } else {
+        (insertions + removals) as f64 / 2.0 / busiest as f64
+      },
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::mapping::binmap_down;
+
+  #[test]
+  fn traffic_of_a_class_is_counted() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let class = binmap_down(64).flat_index();
+    assert_eq!(allocator.monitor_bin_churn(class).churn_ratio, 0.0);
+    let first = allocator.allocate(64, 8).unwrap();
+    allocator.allocate(64, 8).unwrap();
+    for _ in 0..3 {
+      allocator.deallocate(first);
+      assert_eq!(allocator.allocate(64, 8), Some(first));
+    }
+    allocator.deallocate(first);
+    let report = allocator.monitor_bin_churn(class);
+    assert_eq!((report.insertions, report.removals), (4, 3));
+    assert_eq!(report.churn_ratio, 7.0 / 2.0 / 4.0);
+    assert_eq!(allocator.monitor_bin_churn(usize::MAX).insertions, 0);
+  }
+}
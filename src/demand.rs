@@ -0,0 +1,192 @@
+use std::f64::consts::LN_2;
+
+use crate::allocator::SpeedAllocator;
+
+const DEFAULT_HALF_LIFE: u32 = 256;
+
+/// Recent demand on an allocator, see [`SpeedAllocator::demand_estimate`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DemandEstimate {
+  /// Average bytes allocated per allocation request, failed requests counting as zero.
+  pub avg_request_bytes: f64,
+  /// Allocation requests, failed ones included, per `half_life` operations.
+  pub requests_per_epoch: f64,
+  /// Fraction of allocation requests that failed.
+  pub failure_rate: f64,
+}
+
+// Exponential moving averages over operations, an operation being an allocation request or a free. Each
+// average moves by `alpha * (sample - average)` per sample, with `alpha = ln 2 / half_life`: the first-order
+// approximation of `1 - 2^(-1 / half_life)`, kept because it only takes an IEEE division, whose result is the
+// same on every platform, where `powf` is not.
+#[derive(Clone)]
+pub(crate) struct DemandTracker {
+  half_life: u32,
+  alpha: f64,
+  // bytes successfully allocated per operation
+  bytes: f64,
+  // allocation requests per operation
+  requests: f64,
+  // failures per allocation request
+  failures: f64,
+  min_chunk: usize,
+  max_chunk: usize,
+}
+
+impl Default for DemandTracker {
+  fn default() -> Self {
+    DemandTracker {
+      half_life: DEFAULT_HALF_LIFE,
+      alpha: alpha(DEFAULT_HALF_LIFE),
+      bytes: 0.0,
+      requests: 0.0,
+      failures: 0.0,
+      min_chunk: 0,
+      max_chunk: usize::MAX,
+    }
+  }
+}
+
+impl DemandTracker {
+  pub(crate) fn record_allocation(&mut self, size: usize) {
+    self.step(size as f64, 1.0);
+    self.failures += self.alpha * (0.0 - self.failures);
+  }
+
+  pub(crate) fn record_failure(&mut self) {
+    self.step(0.0, 1.0);
+    self.failures += self.alpha * (1.0 - self.failures);
+  }
+
+  pub(crate) fn record_free(&mut self) {
+    self.step(0.0, 0.0);
+  }
+
+  fn step(&mut self, bytes: f64, requests: f64) {
+    self.bytes += self.alpha * (bytes - self.bytes);
+    self.requests += self.alpha * (requests - self.requests);
+  }
+}
+
+fn alpha(half_life: u32) -> f64 {
+  (LN_2 / half_life as f64).min(1.0)
+}
+
+impl SpeedAllocator {
+  /// Moving averages of the recent demand, weighting an operation about half as much once `half_life` more
+  /// operations (allocation requests and frees, 256 by default) have happened, so the figures only depend on
+  /// the sequence of calls and never on wall time. All averages start at zero and are updated as
+  /// `avg += alpha * (sample - avg)` with `alpha = min(ln 2 / half_life, 1)`:
+  ///
+  /// - per operation, with the request size rounded up to 8 bytes for a successful allocation or `0` otherwise,
+  ///   giving `bytes`
+  /// - per operation, with `1` for an allocation request or `0` for a free, giving `requests`
+  /// - per allocation request, with `1` for a failure or `0` for a success, giving `failure_rate`
+  ///
+  /// `avg_request_bytes` is `bytes / requests` (`0` before the first request) and `requests_per_epoch` is
+  /// `requests * half_life`. Only failures of [`allocate`](Self::allocate) and the calls built on it count.
+  pub fn demand_estimate(&self) -> DemandEstimate {
+    let demand = &self.demand;
+    DemandEstimate {
+      avg_request_bytes: if demand.requests == 0.0 {
+        0.0
+      } else {
+        demand.bytes / demand.requests
+      },
+      requests_per_epoch: demand.requests * demand.half_life as f64,
+      failure_rate: demand.failures,
+    }
+  }
+
+  /// Pool size for the next chunk: the bytes in use plus the bytes expected to be requested over the next
+  /// `half_life` operations, `(used_bytes + bytes * half_life) * (1 + target_headroom)`, rounded up to a power of
+  /// two and clamped to the limits set by [`set_chunk_size_limits`](Self::set_chunk_size_limits).
+  pub fn suggest_pool_size(&self, target_headroom: f32) -> usize {
+    let demand = &self.demand;
+    let used = (self.pool.len() - self.free_bytes) as f64;
+    let wanted = (used + demand.bytes * demand.half_life as f64) * (1.0 + target_headroom.max(0.0) as f64);
+    (wanted.ceil() as usize)
+      .checked_next_power_of_two()
+      .unwrap_or(usize::MAX)
+      .clamp(demand.min_chunk, demand.max_chunk)
+  }
+
+  /// Operations after which a sample weighs half as much in [`demand_estimate`](Self::demand_estimate). The
+  /// averages gathered so far are kept.
+  ///
+  /// # Panics
+  ///
+  /// If `operations` is zero.
+  pub fn set_demand_half_life(&mut self, operations: u32) {
+    assert!(operations > 0, "demand half-life must be at least one operation");
+    self.demand.half_life = operations;
+    self.demand.alpha = alpha(operations);
+  }
+
+  /// Smallest and largest size [`suggest_pool_size`](Self::suggest_pool_size) may return.
+  ///
+  /// # Panics
+  ///
+  /// If `min` is above `max`.
+  pub fn set_chunk_size_limits(&mut self, min: usize, max: usize) {
+    assert!(min <= max, "minimum chunk size {min} is above the maximum {max}");
+    self.demand.min_chunk = min;
+    self.demand.max_chunk = max;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn averages_follow_the_documented_updates() {
+    let mut allocator = SpeedAllocator::new(4096);
+    assert_eq!(
+      allocator.demand_estimate(),
+      DemandEstimate { avg_request_bytes: 0.0, requests_per_epoch: 0.0, failure_rate: 0.0 }
+    );
+    let a = LN_2 / DEFAULT_HALF_LIFE as f64;
+    let ptr = allocator.allocate(60, 8).unwrap();
+    let estimate = allocator.demand_estimate();
+    assert_eq!(estimate.avg_request_bytes, 64.0);
+    assert_eq!(estimate.requests_per_epoch, a * DEFAULT_HALF_LIFE as f64);
+    assert_eq!(estimate.failure_rate, 0.0);
+
+    assert_eq!(allocator.allocate(8192, 8), None);
+    let estimate = allocator.demand_estimate();
+    assert!((estimate.avg_request_bytes - 64.0 * (1.0 - a) / (2.0 - a)).abs() < 1e-9);
+    assert_eq!(estimate.failure_rate, a);
+
+    allocator.deallocate(ptr);
+    let requests = (a + a * (1.0 - a)) * (1.0 - a);
+    assert!((allocator.demand_estimate().requests_per_epoch - requests * DEFAULT_HALF_LIFE as f64).abs() < 1e-9);
+  }
+
+  #[test]
+  fn suggested_pool_size_is_a_clamped_power_of_two() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.allocate(64, 8).unwrap();
+    // 64 bytes in use and 64 * ln 2 expected over the next half-life
+    assert_eq!(allocator.suggest_pool_size(0.0), 128);
+    assert_eq!(allocator.suggest_pool_size(1.0), 256);
+    allocator.set_chunk_size_limits(512, 1024);
+    assert_eq!(allocator.suggest_pool_size(0.0), 512);
+    assert_eq!(allocator.suggest_pool_size(100.0), 1024);
+  }
+
+  #[test]
+  fn failures_weigh_about_half_after_a_half_life() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_demand_half_life(4);
+    assert_eq!(allocator.allocate(8192, 8), None);
+    let failed = allocator.demand_estimate().failure_rate;
+    for _ in 0..4 {
+      let ptr = allocator.allocate(64, 8).unwrap();
+      allocator.deallocate(ptr);
+    }
+    // `alpha` is only a first-order approximation, so a bit less than half is left
+    let left = allocator.demand_estimate().failure_rate / failed;
+    assert!((0.45..0.5).contains(&left), "{left}");
+  }
+}
@@ -0,0 +1,67 @@
+use std::fmt::Write;
+
+use crate::allocator::SpeedAllocator;
+use crate::mapping::{BinRef, BIN_COUNT, SUB_BIN};
+
+impl SpeedAllocator {
+  /// Prints [`bin_table`](Self::bin_table) to stderr.
+  pub fn print_bin_table(&self) {
+    eprint!("{}", self.bin_table());
+  }
+
+  /// One row per non-empty sub-bin: flat index, bin, sub-bin, the size range it holds, how many free blocks it
+  /// has and the offset of the first one.
+  pub fn bin_table(&self) -> String {
+    let mut table = format!(
+      "{:>5} | {:>3} | {:>7} | {:>20} | {:>20} | {:>10} | {:>20}\n",
+      "idx", "bin", "sub-bin", "min_size", "max_size", "free_count", "head_offset"
+    );
+    for bin in (0..BIN_COUNT).filter(|&bin| self.bin_bitmap.test(bin)) {
+      for sub_bin in (0..SUB_BIN).filter(|&sub_bin| self.sub_bin_bitmap[bin].test(sub_bin)) {
+        let idx = bin * SUB_BIN + sub_bin;
+        let (min_size, max_size) = class_bounds(bin, sub_bin);
+        let free_count = self.free_list(idx).count();
+        let head_offset = self.free_list(idx).next().map_or(0, |block| block.offset);
+        let _ = writeln!(
+          table,
+          "{idx:>5} | {bin:>3} | {sub_bin:>7} | {min_size:>20} | {max_size:>20} | {free_count:>10} | {head_offset:>20}"
+        );
+      }
+    }
+    table
+  }
+}
+
+// Inclusive range of block sizes stored in a sub-bin.
+fn class_bounds(bin: usize, sub_bin: usize) -> (usize, usize) {
+  let class = BinRef::from_index(bin * SUB_BIN + sub_bin);
+  (class.rounded_size(), class.end().map_or(usize::MAX, |end| end - 1))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn table_has_a_row_per_non_empty_class() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptrs: Vec<_> = (0..4).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+    allocator.deallocate(ptrs[0]);
+    allocator.deallocate(ptrs[2]);
+    let table = allocator.bin_table();
+    let rows: Vec<Vec<&str>> = table
+      .lines()
+      .skip(1)
+      .map(|row| row.split('|').map(str::trim).collect())
+      .collect();
+    // the two 64-byte holes share a row, the tail of 3840 bytes has its own
+    assert_eq!(
+      rows,
+      [
+        ["8", "0", "8", "64", "71", "2", "128"],
+        ["156", "4", "28", "3840", "3903", "1", "256"]
+      ]
+    );
+    assert!(table.starts_with("  idx | bin | sub-bin |"));
+  }
+}
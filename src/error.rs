@@ -0,0 +1,78 @@
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AllocError {
+  /// The pointer isn't the start of a live allocation from this allocator.
+  UnknownAllocation,
+  /// The offset index points at a block that doesn't start at the looked-up offset. The allocator is poisoned
+  /// from then on.
+  IndexCorrupted { offset: usize },
+  /// An earlier operation found the bookkeeping inconsistent; see [`SpeedAllocator::rebuild_index`].
+  ///
+  /// [`SpeedAllocator::rebuild_index`]: crate::SpeedAllocator::rebuild_index
+  Poisoned,
+  /// The requested pool can't hold a single minimum-sized allocation of 8 bytes.
+  PoolTooSmall { size: usize },
+  /// An alignment that isn't a power of two.
+  InvalidAlignment { alignment: usize },
+  /// An alignment above what the [`AlignmentOrigin`](crate::AlignmentOrigin) can guarantee.
+  AlignmentTooLarge { alignment: usize, max: usize },
+  /// No free block can hold the request.
+  OutOfMemory { size: usize, alignment: usize },
+  /// The request needed more work than its [`OperationBudget`](crate::OperationBudget) allows.
+  BudgetExceeded,
+  /// The request asks for something this allocator doesn't implement, see
+  /// [`MemoryAllocator`](crate::MemoryAllocator).
+  Unsupported { what: &'static str },
+  /// Pinning `size` more bytes would take the pinned total past the limit of
+  /// [`SpeedAllocator::set_max_pinned_bytes`](crate::SpeedAllocator::set_max_pinned_bytes).
+  PinLimitExceeded { size: usize, pinned: usize, limit: usize },
+}
+
+impl fmt::Display for AllocError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      AllocError::UnknownAllocation => write!(f, "pointer is not a live allocation"),
+      AllocError::IndexCorrupted { offset } => write!(f, "allocation index is corrupted at offset {offset}"),
+      AllocError::Poisoned => write!(f, "allocator is poisoned by an earlier inconsistency"),
+      AllocError::PoolTooSmall { size } => write!(f, "pool of {size} bytes is too small to hold an allocation"),
+      AllocError::InvalidAlignment { alignment } => write!(f, "alignment {alignment} is not a power of two"),
+      AllocError::AlignmentTooLarge { alignment, max } => {
+        write!(
+          f,
+          "alignment {alignment} is above the {max} guaranteed by the alignment origin"
+        )
+      }
+      AllocError::OutOfMemory { size, alignment } => {
+        write!(f, "no free block holds {size} bytes aligned to {alignment}")
+      }
+      AllocError::BudgetExceeded => write!(f, "allocation ran out of its operation budget"),
+      AllocError::Unsupported { what } => write!(f, "unsupported by this allocator: {what}"),
+      AllocError::PinLimitExceeded { size, pinned, limit } => {
+        write!(
+          f,
+          "pinning {size} bytes would exceed the pin limit of {limit} bytes, {pinned} are pinned"
+        )
+      }
+    }
+  }
+}
+
+impl std::error::Error for AllocError {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn messages_name_the_figures() {
+    assert_eq!(
+      AllocError::OutOfMemory { size: 64, alignment: 16 }.to_string(),
+      "no free block holds 64 bytes aligned to 16"
+    );
+    assert_eq!(
+      AllocError::PinLimitExceeded { size: 64, pinned: 128, limit: 128 }.to_string(),
+      "pinning 64 bytes would exceed the pin limit of 128 bytes, 128 are pinned"
+    );
+  }
+}
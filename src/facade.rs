@@ -0,0 +1,231 @@
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::allocator::{SpeedAllocator, Stats};
+use crate::block::BlockId;
+use crate::error::AllocError;
+use crate::validate::ValidationError;
+
+/// Options of a [`Request`], combined with `|`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RequestFlags(u32);
+
+impl RequestFlags {
+  pub const NONE: RequestFlags = RequestFlags(0);
+  /// Only serve the request from a block it fills without leaving a remainder to split off.
+  pub const NO_SPLIT: RequestFlags = RequestFlags(1);
+  /// Zero the allocation before handing it out.
+  pub const ZEROED: RequestFlags = RequestFlags(1 << 1);
+
+  pub const fn contains(self, other: RequestFlags) -> bool {
+    self.0 & other.0 == other.0
+  }
+
+  /// Flags of `self` missing from `supported`.
+  pub const fn difference(self, supported: RequestFlags) -> RequestFlags {
+    RequestFlags(self.0 & !supported.0)
+  }
+
+  pub const fn is_empty(self) -> bool {
+    self.0 == 0
+  }
+}
+
+impl std::ops::BitOr for RequestFlags {
+  type Output = RequestFlags;
+
+  fn bitor(self, other: RequestFlags) -> RequestFlags {
+    RequestFlags(self.0 | other.0)
+  }
+}
+
+/// What to allocate through [`MemoryAllocator::allocate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Request {
+  pub size: usize,
+  pub alignment: usize,
+  pub flags: RequestFlags,
+  #[cfg_attr(
+    feature = "tagging",
+    doc = "Tag to attach to the allocation, as with [`SpeedAllocator::allocate_tagged`]."
+  )]
+  #[cfg_attr(
+    not(feature = "tagging"),
+    doc = "Tag to attach to the allocation; needs the `tagging` feature, the request fails without it."
+  )]
+  pub tag: Option<u64>,
+}
+
+impl Request {
+  pub fn new(size: usize, alignment: usize) -> Request {
+    Request { size, alignment, flags: RequestFlags::NONE, tag: None }
+  }
+}
+
+/// An allocation made through [`MemoryAllocator::allocate`], handed back to
+/// [`MemoryAllocator::deallocate`] to free it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Allocation {
+  pub ptr: NonNull<u8>,
+  /// Usable bytes at `ptr`, at least the requested size.
+  pub size: usize,
+  /// [`SpeedAllocator::epoch`] when the allocation was made, see [`SpeedAllocator::is_current`].
+  pub epoch: u64,
+  // id of the allocator that made the allocation
+  pub(crate) allocator_id: u64,
+  // block and allocation id behind `SpeedAllocator::resolve_handle`; only the id for a `BumpAllocator`
+  pub(crate) block: BlockId,
+  pub(crate) alloc_id: u64,
+}
+
+// Id for a new allocator, never handed out twice in a process.
+pub(crate) fn next_allocator_id() -> u64 {
+  static NEXT: AtomicU64 = AtomicU64::new(1);
+  NEXT.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Object-safe interface over allocator implementations, for callers that pick one at runtime behind a
+/// `Box<dyn MemoryAllocator>`.
+///
+/// A request using a flag or a tag the implementation can't honour fails with [`AllocError::Unsupported`]
+/// instead of being served without it.
+pub trait MemoryAllocator {
+  fn allocate(&mut self, request: Request) -> Result<Allocation, AllocError>;
+  fn deallocate(&mut self, allocation: Allocation) -> Result<(), AllocError>;
+  fn stats(&self) -> Stats;
+  fn validate(&self) -> Result<(), ValidationError>;
+}
+
+impl MemoryAllocator for SpeedAllocator {
+  fn allocate(&mut self, request: Request) -> Result<Allocation, AllocError> {
+    let Request { size, alignment, flags, tag } = request;
+    if !flags
+      .difference(RequestFlags::NO_SPLIT | RequestFlags::ZEROED)
+      .is_empty()
+    {
+      return Err(AllocError::Unsupported { what: "request flag" });
+    }
+    if tag.is_some() && cfg!(not(feature = "tagging")) {
+      return Err(AllocError::Unsupported { what: "tags without the `tagging` feature" });
+    }
+    let ptr = if flags.contains(RequestFlags::NO_SPLIT) {
+      if self.poisoned.get() {
+        return Err(AllocError::Poisoned);
+      }
+      self
+        .allocate_no_split(size, alignment)
+        .ok_or(AllocError::OutOfMemory { size, alignment })?
+    } else {
+      self.try_allocate(size, alignment)?
+    };
+    let allocation = self.allocation_handle(ptr).ok_or(AllocError::UnknownAllocation)?;
+    if flags.contains(RequestFlags::ZEROED) {
+      let offset = self.pool.offset_of(ptr).ok_or(AllocError::UnknownAllocation)?;
+      self.pool.fill(offset, allocation.size, 0);
+    }
+    #[cfg(feature = "tagging")]
+    self.set_tag(allocation.block, tag);
+    Ok(allocation)
+  }
+
+  // The handle must still name its block and allocation: a stale one whose address was reused fails instead of
+  // freeing the newer allocation.
+  fn deallocate(&mut self, allocation: Allocation) -> Result<(), AllocError> {
+    let block = self.resolve_handle(&allocation)?;
+    if self.pool.offset_of(allocation.ptr) != Some(block.offset + block.alignment_waste) {
+      return Err(AllocError::UnknownAllocation);
+    }
+    self.try_deallocate(allocation.ptr)
+  }
+
+  fn stats(&self) -> Stats {
+    SpeedAllocator::stats(self)
+  }
+
+  fn validate(&self) -> Result<(), ValidationError> {
+    SpeedAllocator::validate(self)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::BumpAllocator;
+
+  fn implementations() -> Vec<(&'static str, Box<dyn MemoryAllocator>)> {
+    vec![
+      ("speed", Box::new(SpeedAllocator::new(1 << 16))),
+      ("bump", Box::new(BumpAllocator::new(1 << 16))),
+    ]
+  }
+
+  // Workload every implementation has to get through the same way.
+  fn run_workload(allocator: &mut dyn MemoryAllocator) {
+    let mut live = Vec::new();
+    for (i, size) in [1, 24, 100, 256, 1000, 4000].into_iter().enumerate() {
+      let request = Request { flags: RequestFlags::ZEROED, ..Request::new(size, 8 << (i % 4)) };
+      let allocation = allocator.allocate(request).unwrap();
+      assert!(allocation.size >= size);
+      assert!((allocation.ptr.as_ptr() as usize).is_multiple_of(request.alignment));
+      live.push(allocation);
+    }
+    assert_eq!(allocator.stats().live_allocations, live.len());
+    assert_eq!(allocator.validate(), Ok(()));
+
+    let freed = live.remove(2);
+    allocator.deallocate(freed).unwrap();
+    assert_eq!(allocator.deallocate(freed), Err(AllocError::UnknownAllocation));
+    assert!(matches!(
+      allocator.allocate(Request::new(1 << 20, 8)),
+      Err(AllocError::OutOfMemory { .. })
+    ));
+    for allocation in live {
+      allocator.deallocate(allocation).unwrap();
+    }
+    assert_eq!(allocator.stats().live_allocations, 0);
+    assert_eq!(allocator.stats().used_bytes, 0);
+    assert_eq!(allocator.validate(), Ok(()));
+  }
+
+  #[test]
+  fn every_implementation_runs_the_shared_workload() {
+    for (_, mut allocator) in implementations() {
+      run_workload(allocator.as_mut());
+    }
+  }
+
+  #[test]
+  fn stale_handle_does_not_free_the_allocation_reusing_its_address() {
+    for (name, mut allocator) in implementations() {
+      let stale = allocator.allocate(Request::new(64, 8)).unwrap();
+      allocator.deallocate(stale).unwrap();
+      let reused = allocator.allocate(Request::new(64, 8)).unwrap();
+      assert_eq!(reused.ptr, stale.ptr, "{name}");
+      assert_eq!(
+        allocator.deallocate(stale),
+        Err(AllocError::UnknownAllocation),
+        "{name}"
+      );
+      assert_eq!(allocator.stats().live_allocations, 1, "{name}");
+      allocator.deallocate(reused).unwrap();
+    }
+  }
+
+  #[test]
+  fn unsupported_requests_fail() {
+    let mut bump = BumpAllocator::new(4096);
+    let no_split = Request { flags: RequestFlags::NO_SPLIT, ..Request::new(64, 8) };
+    assert_eq!(
+      bump.allocate(no_split),
+      Err(AllocError::Unsupported { what: "request flag" })
+    );
+    let tagged = Request { tag: Some(1), ..Request::new(64, 8) };
+    assert_eq!(bump.allocate(tagged), Err(AllocError::Unsupported { what: "tags" }));
+    let mut speed = SpeedAllocator::new(4096);
+    let unknown = Request { flags: RequestFlags(1 << 7), ..Request::new(64, 8) };
+    assert_eq!(
+      MemoryAllocator::allocate(&mut speed, unknown),
+      Err(AllocError::Unsupported { what: "request flag" })
+    );
+  }
+}
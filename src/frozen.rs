@@ -0,0 +1,227 @@
+use std::sync::Arc;
+
+use crate::allocator::SpeedAllocator;
+use crate::iter::BlockInfo;
+use crate::mapping::MIN_ALLOC_SIZE;
+use crate::plan::{CompactionPlan, Relocation};
+
+/// Immutable copy of an allocator's block layout taken by [`SpeedAllocator::freeze`]. Cloning shares the
+/// copy, so the view can be handed to another thread to answer layout queries while the allocator keeps
+/// changing.
+#[derive(Clone, Debug)]
+pub struct FrozenView {
+  blocks: Arc<[BlockInfo]>,
+  pool_size: usize,
+  // what offsets are added to before checking their alignment, see `SpeedAllocator::alignment_base`
+  alignment_base: usize,
+  generation: u64,
+  live_allocations: usize,
+}
+
+impl FrozenView {
+  /// [`SpeedAllocator::generation`] at the time of the freeze. Work planned against the view is still valid
+  /// for the allocator as long as `allocator.has_changed_since(view.generation())` is `false`.
+  pub fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  /// Every block in offset order.
+  pub fn blocks(&self) -> &[BlockInfo] {
+    &self.blocks
+  }
+
+  pub fn pool_size(&self) -> usize {
+    self.pool_size
+  }
+
+  pub fn free_bytes(&self) -> usize {
+    self.free_blocks().map(|block| block.size).sum()
+  }
+
+  pub fn largest_free_block(&self) -> usize {
+    self.free_blocks().map(|block| block.size).max().unwrap_or(0)
+  }
+
+  /// Same as [`Stats::live_allocations`](crate::Stats::live_allocations) at the time of the freeze.
+  pub fn live_allocations(&self) -> usize {
+    self.live_allocations
+  }
+
+  /// Same as [`Stats::external_fragmentation`](crate::Stats::external_fragmentation) at the time of the freeze.
+  pub fn external_fragmentation(&self) -> f64 {
+    match self.free_bytes() {
+      0 => 0.0,
+      free => 1.0 - self.largest_free_block() as f64 / free as f64,
+    }
+  }
+
+  /// Same as [`SpeedAllocator::occupancy_map`] at the time of the freeze.
+  pub fn occupancy_map(&self, granularity: usize) -> Vec<bool> {
+    occupancy_map(self.blocks.iter().copied(), self.pool_size, granularity)
+  }
+
+  /// Plans sliding every live allocation towards the start of the pool, past the free space before it, keeping
+  /// it at least as aligned as it is now against the allocator's [`AlignmentOrigin`](crate::AlignmentOrigin), so
+  /// a host pointer stays as aligned under [`AlignmentOrigin::Host`](crate::AlignmentOrigin::Host). Pinned
+  /// allocations and blocks held back without an allocation, such as quarantined ones, stay in place. The plan
+  /// carries the view's generation and is applied with [`SpeedAllocator::commit_plan`], which checks every step
+  /// against the allocator as it is by then.
+  pub fn plan_compaction(&self) -> CompactionPlan {
+    let mut plan = CompactionPlan { generation: self.generation, relocations: Vec::new(), alloc_ids: Vec::new() };
+    // start of the space the next allocation may slide into
+    let mut cursor = 0;
+    for block in self.blocks.iter() {
+      let end = block.offset + block.size;
+      if block.is_free {
+        continue;
+      }
+      let from = block.offset + block.alignment_waste;
+      if block.alloc_id == 0 || block.pinned || cursor == block.offset {
+        cursor = end;
+        continue;
+      }
+      let base = self.alignment_base;
+      let alignment = 1usize << base.wrapping_add(from).trailing_zeros().min(usize::BITS - 1);
+      let to = cursor + alignment.wrapping_sub(base.wrapping_add(cursor)) % alignment;
+      let size = end - from;
+      if to >= from {
+        cursor = end;
+        continue;
+      }
+      plan.relocations.push(Relocation { from, to, size });
+      plan.alloc_ids.push(block.alloc_id);
+      cursor = (to + size).next_multiple_of(MIN_ALLOC_SIZE);
+    }
+    plan
+  }
+
+  fn free_blocks(&self) -> impl Iterator<Item = &BlockInfo> {
+    self.blocks.iter().filter(|block| block.is_free)
+  }
+}
+
+// One entry per `granularity`-sized slot of a pool of `pool_size` bytes, `true` when a used block overlaps it.
+pub(crate) fn occupancy_map(
+  blocks: impl Iterator<Item = BlockInfo>,
+  pool_size: usize,
+  granularity: usize,
+) -> Vec<bool> {
+  assert!(granularity > 0, "granularity must be non-zero");
+  let mut map = vec![false; pool_size.div_ceil(granularity)];
+  for block in blocks.filter(|block| !block.is_free) {
+    let first = block.offset / granularity;
+    let last = (block.offset + block.size - 1) / granularity;
+    map[first..=last].fill(true);
+  }
+  map
+}
+
+impl SpeedAllocator {
+  /// Copies the block layout into a [`FrozenView`] in one pass over the blocks. The copy lives in its own host
+  /// allocation; no block node is created.
+  pub fn freeze(&self) -> FrozenView {
+    FrozenView {
+      blocks: self.blocks().collect(),
+      pool_size: self.pool.len(),
+      alignment_base: self.alignment_base(),
+      generation: self.generation,
+      live_allocations: self.allocations.len(),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{AlignmentOrigin, PlanError};
+
+  #[test]
+  fn live_allocations_leave_out_quarantined_blocks() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_quarantine_depth(4);
+    let a = allocator.allocate(64, 8).unwrap();
+    allocator.allocate(64, 8).unwrap();
+    allocator.deallocate(a);
+    assert_eq!(allocator.freeze().live_allocations(), 1);
+  }
+
+  #[test]
+  fn commit_rejects_only_the_steps_invalidated_since_the_freeze() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_alignment_origin(AlignmentOrigin::PoolRelative);
+    let ptrs: Vec<_> = (0..6).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+    for (i, &ptr) in ptrs.iter().enumerate() {
+      assert!(allocator.fill_allocation_pattern_and_check(ptr, &[i as u8]));
+    }
+    for &i in &[0, 2, 4] {
+      allocator.deallocate(ptrs[i]);
+    }
+    let plan = allocator.freeze().plan_compaction();
+    let moves: Vec<_> = plan.relocations().iter().map(|r| (r.from, r.to)).collect();
+    assert_eq!(moves, [(64, 0), (192, 64), (320, 128)]);
+    assert_eq!(allocator.validate_plan(plan.relocations()), Ok(()));
+
+    allocator.deallocate(ptrs[1]);
+    allocator.allocate(64, 8).unwrap();
+    assert!(allocator.has_changed_since(plan.generation()));
+    let results = allocator.commit_plan(&plan);
+    assert_eq!(results[0], Err(PlanError::UnknownAllocation { step: 0, from: 64 }));
+    assert_eq!(allocator.check_result(results[1].clone().unwrap(), &[3]), Some(0));
+    assert_eq!(allocator.check_result(results[2].clone().unwrap(), &[5]), Some(0));
+    assert_eq!(allocator.stats().live_allocations, 3);
+    assert_eq!(allocator.validate(), Ok(()));
+  }
+
+  #[test]
+  fn commit_rejects_a_step_whose_offset_was_reused() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_alignment_origin(AlignmentOrigin::PoolRelative);
+    let a = allocator.allocate(64, 8).unwrap();
+    let b = allocator.allocate(64, 8).unwrap();
+    allocator.deallocate(a);
+    let plan = allocator.freeze().plan_compaction();
+    allocator.deallocate(b);
+    allocator.allocate(64, 8).unwrap();
+    allocator.allocate(64, 8).unwrap();
+    assert_eq!(
+      allocator.commit_plan(&plan),
+      [Err(PlanError::Replaced { step: 0, from: 64 })]
+    );
+    assert_eq!(allocator.validate(), Ok(()));
+  }
+
+  #[test]
+  fn compaction_keeps_host_pointers_aligned() {
+    let mut allocator = SpeedAllocator::new(1 << 16);
+    let pad = allocator.allocate(8192, 8).unwrap();
+    let ptr = allocator.allocate(64, 4096).unwrap();
+    allocator.deallocate(pad);
+    let from = allocator.pool.offset_of(ptr).unwrap();
+    // the plan keeps the host address at least as aligned as it is, which may leave no room below it
+    let alignment = 1 << (allocator.alignment_base() + from).trailing_zeros();
+    let plan = allocator.freeze().plan_compaction();
+    assert_eq!(plan.relocations().len(), (from >= alignment) as usize);
+    let moved: Vec<_> = allocator.commit_plan(&plan).into_iter().map(Result::unwrap).collect();
+    for ptr in moved
+      .into_iter()
+      .chain([ptr].into_iter().filter(|_| plan.relocations().is_empty()))
+    {
+      assert!((ptr.as_ptr() as usize).is_multiple_of(4096), "{ptr:?}");
+    }
+    assert_eq!(allocator.validate(), Ok(()));
+  }
+
+  #[test]
+  fn compaction_keeps_absolute_offsets_aligned() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_alignment_origin(AlignmentOrigin::Absolute { base_offset: 24, base_alignment: 4096 });
+    let pad = allocator.allocate(512, 8).unwrap();
+    let ptr = allocator.allocate(64, 256).unwrap();
+    allocator.deallocate(pad);
+    assert_eq!(allocator.pool.offset_of(ptr), Some(744));
+    let plan = allocator.freeze().plan_compaction();
+    assert_eq!(plan.relocations(), [Relocation { from: 744, to: 232, size: 64 }]);
+    assert!(allocator.commit_plan(&plan)[0].is_ok());
+    assert_eq!(allocator.validate(), Ok(()));
+  }
+}
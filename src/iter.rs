@@ -0,0 +1,123 @@
+use crate::block::{Block, BlockId, Blocks};
+
+/// Copy of a block's public state, detached from the allocator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockInfo {
+  pub offset: usize,
+  pub size: usize,
+  pub is_free: bool,
+  /// Bytes skipped at the start of a used block to align the allocation.
+  pub alignment_waste: usize,
+  /// Sequence number of the allocation in a used block, `0` for free blocks.
+  pub alloc_id: u64,
+  /// Tag of the allocation in a used block, `0` when untagged, free, or the `tagging` feature is off.
+  pub tag: u64,
+  /// The allocation in a used block is [pinned](crate::SpeedAllocator::pin).
+  pub pinned: bool,
+}
+
+impl BlockInfo {
+  pub(crate) fn new(block: &Block) -> Self {
+    BlockInfo {
+      offset: block.offset,
+      size: block.size,
+      is_free: block.is_free,
+      alignment_waste: block.adjustment,
+      alloc_id: block.alloc_id,
+      tag: block.tag(),
+      pinned: block.pinned,
+    }
+  }
+}
+
+/// Iterator over the physical chain of an allocator, walkable from both ends.
+pub struct PhysicalBlockIter<'a> {
+  blocks: &'a Blocks,
+  front: Option<BlockId>,
+  back: Option<BlockId>,
+}
+
+impl<'a> PhysicalBlockIter<'a> {
+  pub(crate) fn new(blocks: &'a Blocks, head: BlockId, tail: BlockId) -> Self {
+    PhysicalBlockIter { blocks, front: Some(head), back: Some(tail) }
+  }
+}
+
+impl Iterator for PhysicalBlockIter<'_> {
+  type Item = BlockInfo;
+
+  fn next(&mut self) -> Option<BlockInfo> {
+    let block = &self.blocks[self.front?];
+    if self.front == self.back {
+      self.front = None;
+      self.back = None;
+    } else {
+      self.front = block.next_physical;
+    }
+    Some(BlockInfo::new(block))
+  }
+}
+
+impl DoubleEndedIterator for PhysicalBlockIter<'_> {
+  fn next_back(&mut self) -> Option<BlockInfo> {
+    let block = &self.blocks[self.back?];
+    if self.front == self.back {
+      self.front = None;
+      self.back = None;
+    } else {
+      self.back = block.prev_physical;
+    }
+    Some(BlockInfo::new(block))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::SpeedAllocator;
+
+  #[test]
+  fn both_ends_meet_without_repeating_a_block() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptrs: Vec<_> = (0..4).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+    allocator.deallocate(ptrs[1]);
+    let offsets: Vec<_> = allocator.blocks().map(|block| block.offset).collect();
+    assert_eq!(offsets, [0, 64, 128, 192, 256]);
+
+    let reversed: Vec<_> = allocator
+      .physical_chain_reverse_iterator()
+      .map(|block| block.offset)
+      .collect();
+    assert_eq!(reversed, [256, 192, 128, 64, 0]);
+    let mut iter = allocator.blocks();
+    let mut alternating = Vec::new();
+    while let Some(block) = iter.next() {
+      alternating.push(block.offset);
+      alternating.extend(iter.next_back().map(|block| block.offset));
+    }
+    assert_eq!(alternating, [0, 256, 64, 192, 128]);
+    assert!(iter.next().is_none() && iter.next_back().is_none());
+
+    let info: Vec<_> = allocator
+      .blocks()
+      .map(|block| (block.is_free, block.alloc_id != 0))
+      .collect();
+    assert_eq!(
+      info,
+      [
+        (false, true),
+        (true, false),
+        (false, true),
+        (false, true),
+        (true, false)
+      ]
+    );
+  }
+
+  #[test]
+  fn single_block_is_yielded_once() {
+    let allocator = SpeedAllocator::new(64);
+    let mut iter = allocator.blocks();
+    assert_eq!(iter.next_back().map(|block| block.size), Some(64));
+    assert!(iter.next().is_none());
+  }
+}
@@ -1 +1,62 @@
+#![deny(unsafe_code, unsafe_op_in_unsafe_fn)]
 
+mod adopt;
+mod allocator;
+mod arena;
+mod benchmark;
+mod bitmap;
+mod block;
+mod budget;
+mod bump;
+#[cfg(feature = "churn-monitoring")]
+mod churn;
+#[cfg(feature = "perf-counters")]
+mod demand;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+mod error;
+mod facade;
+mod frozen;
+mod iter;
+mod mapping;
+mod pin;
+mod plan;
+#[allow(unsafe_code)]
+mod pool;
+#[cfg(feature = "profiling")]
+mod profile;
+#[cfg(feature = "stats-sampler")]
+mod sampler;
+mod self_check;
+mod state;
+#[cfg(feature = "tagging")]
+mod tagging;
+#[cfg(feature = "time-series")]
+mod timeseries;
+mod validate;
+
+pub use adopt::{AdoptError, LiveRange};
+pub use allocator::{
+  AlignmentOrigin, AllocationReport, AllocatorConfig, FitPolicy, SpeedAllocator, Stats, TracedAllocation,
+};
+pub use arena::FixedSizeArena;
+pub use benchmark::{AllocationPattern, BenchmarkResult, StressTestResult};
+pub use budget::OperationBudget;
+pub use bump::BumpAllocator;
+#[cfg(feature = "churn-monitoring")]
+pub use churn::BinChurnReport;
+#[cfg(feature = "perf-counters")]
+pub use demand::DemandEstimate;
+pub use error::AllocError;
+pub use facade::{Allocation, MemoryAllocator, Request, RequestFlags};
+pub use frozen::FrozenView;
+pub use iter::{BlockInfo, PhysicalBlockIter};
+pub use mapping::BinRef;
+pub use plan::{CompactionPlan, PlanError, Relocation};
+#[cfg(feature = "profiling")]
+pub use profile::{compare_profiles, SizeProfile};
+#[cfg(feature = "stats-sampler")]
+pub use sampler::{LockedSpeedAllocator, QuickStats, StatsSample, StatsSampler};
+pub use self_check::{SelfCheckError, SelfCheckFailure};
+pub use state::RestoreError;
+pub use validate::{ValidationError, ValidationProgress};
@@ -1,3 +0,0 @@
-mod allocator;
-mod block;
-fn main() {}
@@ -0,0 +1,178 @@
+use std::fmt;
+
+// Size classes: bin 0 is split linearly into `SUB_BIN` slots of `MIN_ALLOC_SIZE` bytes, every following bin
+// covers one power of two `[2^(LINEAR + bin - 1), 2^(LINEAR + bin))` split into `SUB_BIN` equal slots.
+pub const SUB_BIN_LOG: u32 = 5;
+pub const SUB_BIN: usize = 1 << SUB_BIN_LOG;
+pub const LINEAR: u32 = 8;
+pub const MIN_ALLOC_SIZE: usize = 1 << (LINEAR - SUB_BIN_LOG);
+pub const BIN_COUNT: usize = (usize::BITS - LINEAR + 1) as usize;
+pub const BLOCK_COUNT: usize = BIN_COUNT * SUB_BIN;
+
+/// One size class: a sub-bin of a bin, holding free blocks whose size falls in
+/// `rounded_size()..rounded_size() + step`.
+///
+/// Displays as `bin 5.0 (4096..4224)`: bin, sub-bin and the half-open range of block sizes the class holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BinRef {
+  bin: usize,
+  sub_bin: usize,
+  // lower bound of the size class
+  rounded_size: usize,
+}
+
+impl BinRef {
+  // class at position `idx` of the flat bin array
+  pub(crate) fn from_index(idx: usize) -> BinRef {
+    let (bin, sub_bin) = (idx / SUB_BIN, idx % SUB_BIN);
+    let (base, step) = class_geometry(bin);
+    BinRef { bin, sub_bin, rounded_size: base + sub_bin * step }
+  }
+
+  pub fn bin(&self) -> usize {
+    self.bin
+  }
+
+  pub fn sub_bin(&self) -> usize {
+    self.sub_bin
+  }
+
+  /// Position of the class in the flat bin array, `bin * 32 + sub_bin`.
+  pub fn flat_index(&self) -> usize {
+    self.bin * SUB_BIN + self.sub_bin
+  }
+
+  /// Smallest block size the class holds.
+  pub fn rounded_size(&self) -> usize {
+    self.rounded_size
+  }
+
+  // exclusive upper bound of the class, `None` for the last class, which runs up to `usize::MAX`
+  pub(crate) fn end(&self) -> Option<usize> {
+    self.rounded_size.checked_add(class_geometry(self.bin).1)
+  }
+}
+
+impl fmt::Display for BinRef {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "bin {}.{} ({}..", self.bin, self.sub_bin, self.rounded_size)?;
+    match self.end() {
+      Some(end) => write!(f, "{end})"),
+      None => write!(f, ")"),
+    }
+  }
+}
+
+// first size and sub-bin width of `bin`
+fn class_geometry(bin: usize) -> (usize, usize) {
+  if bin == 0 {
+    (0, MIN_ALLOC_SIZE)
+  } else {
+    let log = bin as u32 + LINEAR - 1;
+    (1 << log, 1 << (log - SUB_BIN_LOG))
+  }
+}
+
+// class containing `size`, used when inserting a free block
+pub fn binmap_down(size: usize) -> BinRef {
+  if size < 1 << LINEAR {
+    let sub_bin = size / MIN_ALLOC_SIZE;
+    return BinRef { bin: 0, sub_bin, rounded_size: sub_bin * MIN_ALLOC_SIZE };
+  }
+  let log = usize::BITS - 1 - size.leading_zeros();
+  let shift = log - SUB_BIN_LOG;
+  BinRef {
+    bin: (log - LINEAR + 1) as usize,
+    sub_bin: (size >> shift) & (SUB_BIN - 1),
+    rounded_size: size & !((1 << shift) - 1),
+  }
+}
+
+// first class whose every block can hold `size`, used when searching
+pub fn binmap_up(size: usize) -> Option<BinRef> {
+  let step = if size < 1 << LINEAR {
+    MIN_ALLOC_SIZE
+  } else {
+    1 << (usize::BITS - 1 - size.leading_zeros() - SUB_BIN_LOG)
+  };
+  let rounded = size.checked_add(step - 1)? & !(step - 1);
+  let map = binmap_down(rounded);
+  debug_assert!(
+    map.rounded_size >= size,
+    "class for {size} starts below it at {}",
+    map.rounded_size
+  );
+  Some(map)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn binmap_up_never_rounds_below_the_request() {
+    let sizes = (0..1 << 16)
+      .chain((16..usize::BITS).flat_map(|log| {
+        let power = 1usize << log;
+        [power - 1, power, power + 1, power + power / 3]
+      }))
+      .chain([usize::MAX - (usize::MAX >> 6), usize::MAX >> 1]);
+    for size in sizes {
+      let Some(map) = binmap_up(size) else {
+        panic!("no class for {size}");
+      };
+      assert!(map.rounded_size() >= size, "{size} maps to {map}");
+      // and it is the lowest such class
+      if map.flat_index() > 0 {
+        assert!(
+          BinRef::from_index(map.flat_index() - 1).rounded_size() < size,
+          "{size} skips a class"
+        );
+      }
+    }
+    assert_eq!(binmap_up(usize::MAX), None);
+  }
+
+  #[test]
+  fn binmap_down_edges() {
+    let cases = [
+      (0, (0, 0, 0)),
+      (MIN_ALLOC_SIZE - 1, (0, 0, 0)),
+      (MIN_ALLOC_SIZE, (0, 1, MIN_ALLOC_SIZE)),
+      (255, (0, 31, 248)),
+      (256, (1, 0, 256)),
+      (263, (1, 0, 256)),
+      (264, (1, 1, 264)),
+      (511, (1, 31, 504)),
+      (512, (2, 0, 512)),
+      (usize::MAX, (BIN_COUNT - 1, SUB_BIN - 1, usize::MAX - (usize::MAX >> 6))),
+    ];
+    for (size, (bin, sub_bin, rounded_size)) in cases {
+      let map = binmap_down(size);
+      assert_eq!(
+        (map.bin(), map.sub_bin(), map.rounded_size()),
+        (bin, sub_bin, rounded_size),
+        "{size}"
+      );
+      assert_eq!(BinRef::from_index(map.flat_index()), map);
+      assert!(map.end().is_none_or(|end| size < end));
+    }
+  }
+
+  #[test]
+  fn classes_tile_the_sizes_without_gaps() {
+    for idx in 1..BLOCK_COUNT {
+      let (prev, class) = (BinRef::from_index(idx - 1), BinRef::from_index(idx));
+      assert_eq!(prev.end(), Some(class.rounded_size()), "{prev} then {class}");
+    }
+    assert_eq!(BinRef::from_index(BLOCK_COUNT - 1).end(), None);
+  }
+
+  #[test]
+  fn display_names_the_size_range() {
+    assert_eq!(binmap_down(4096).to_string(), "bin 5.0 (4096..4224)");
+    let last = BinRef::from_index(BLOCK_COUNT - 1);
+    let start = usize::MAX - (usize::MAX >> 6);
+    assert_eq!(last.to_string(), format!("bin {}.31 ({start}..)", BIN_COUNT - 1));
+  }
+}
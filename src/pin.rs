@@ -0,0 +1,139 @@
+use crate::allocator::SpeedAllocator;
+use crate::error::AllocError;
+use crate::facade::Allocation;
+
+impl SpeedAllocator {
+  /// Allocates like [`try_allocate`](Self::try_allocate) and pins the allocation right away, as
+  /// [`pin`](Self::pin) does. When pinning it would exceed the limit the allocation is given back and the call
+  /// fails with [`AllocError::PinLimitExceeded`].
+  pub fn allocate_pinned(&mut self, size: usize, alignment: usize) -> Result<Allocation, AllocError> {
+    let ptr = self.try_allocate(size, alignment)?;
+    let allocation = self.allocation_handle(ptr).ok_or(AllocError::UnknownAllocation)?;
+    if let Err(error) = self.pin(&allocation) {
+      self.take_back(ptr);
+      return Err(error);
+    }
+    Ok(allocation)
+  }
+
+  /// Keeps the allocation behind `allocation` where it is, e.g. while a device reads from it:
+  /// [`validate_plan`](Self::validate_plan) and [`commit_plan`](Self::commit_plan) reject steps moving it and
+  /// [`FrozenView::plan_compaction`](crate::FrozenView::plan_compaction) leaves it out, until it is unpinned or
+  /// freed. Pinning counts its whole block towards [`pinned_bytes`](Self::pinned_bytes) and fails with
+  /// [`AllocError::PinLimitExceeded`] past [`set_max_pinned_bytes`](Self::set_max_pinned_bytes). Pinning a pinned
+  /// allocation does nothing. Fails with [`AllocError::UnknownAllocation`] for handles
+  /// [`resolve_handle`](Self::resolve_handle) rejects.
+  pub fn pin(&mut self, allocation: &Allocation) -> Result<(), AllocError> {
+    let block = self.handle_block(allocation).ok_or(AllocError::UnknownAllocation)?;
+    let b = &mut self.blocks[block];
+    if b.pinned {
+      return Ok(());
+    }
+    let limit = self.max_pinned_bytes;
+    let pinned = self.pinned_bytes;
+    match pinned.checked_add(b.size) {
+      Some(total) if total <= limit => {
+        b.pinned = true;
+        self.pinned_bytes = total;
+        self.generation += 1;
+        Ok(())
+      }
+      _ => Err(AllocError::PinLimitExceeded { size: b.size, pinned, limit }),
+    }
+  }
+
+  /// Lets the allocation behind `allocation` move again. Unpinning an allocation that isn't pinned does nothing;
+  /// freeing an allocation unpins it too.
+  pub fn unpin(&mut self, allocation: &Allocation) -> Result<(), AllocError> {
+    let block = self.handle_block(allocation).ok_or(AllocError::UnknownAllocation)?;
+    let b = &mut self.blocks[block];
+    if b.pinned {
+      b.pinned = false;
+      self.pinned_bytes -= b.size;
+      self.generation += 1;
+    }
+    Ok(())
+  }
+
+  /// Bytes of the blocks of every pinned allocation.
+  pub fn pinned_bytes(&self) -> usize {
+    self.pinned_bytes
+  }
+
+  /// Caps [`pinned_bytes`](Self::pinned_bytes), unlimited by default. A cap below the bytes already pinned
+  /// unpins nothing; further pins fail until enough are released.
+  pub fn set_max_pinned_bytes(&mut self, max: usize) {
+    self.max_pinned_bytes = max;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::plan::{PlanError, Relocation};
+  use crate::AlignmentOrigin;
+
+  #[test]
+  fn pinning_past_the_limit_fails_until_a_pin_is_released() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_max_pinned_bytes(128);
+    let first = allocator.allocate_pinned(64, 8).unwrap();
+    let second = allocator.allocate_pinned(64, 8).unwrap();
+    assert_eq!(allocator.pinned_bytes(), 128);
+
+    let used = allocator.stats().used_bytes;
+    assert_eq!(
+      allocator.allocate_pinned(64, 8),
+      Err(AllocError::PinLimitExceeded { size: 64, pinned: 128, limit: 128 })
+    );
+    assert_eq!(allocator.stats().used_bytes, used);
+    let ptr = allocator.allocate(64, 8).unwrap();
+    let third = allocator.allocation_handle(ptr).unwrap();
+    assert!(matches!(
+      allocator.pin(&third),
+      Err(AllocError::PinLimitExceeded { .. })
+    ));
+
+    allocator.unpin(&first).unwrap();
+    assert_eq!(allocator.pinned_bytes(), 64);
+    allocator.pin(&third).unwrap();
+    allocator.pin(&third).unwrap();
+    assert_eq!(allocator.pinned_bytes(), 128);
+
+    allocator.deallocate(second.ptr);
+    assert_eq!(allocator.pinned_bytes(), 64);
+    allocator.validate().unwrap();
+  }
+
+  #[test]
+  fn pinned_allocation_stays_out_of_compaction_until_unpinned() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_alignment_origin(AlignmentOrigin::PoolRelative);
+    let gap = allocator.allocate(64, 8).unwrap();
+    let pinned = allocator.allocate_pinned(64, 8).unwrap();
+    allocator.deallocate(gap);
+
+    assert!(allocator.freeze().plan_compaction().relocations().is_empty());
+    let relocation = Relocation { from: 64, to: 0, size: 64 };
+    assert_eq!(
+      allocator.validate_plan(&[relocation]),
+      Err(PlanError::Pinned { step: 0, from: 64 })
+    );
+
+    let plan = {
+      allocator.unpin(&pinned).unwrap();
+      let plan = allocator.freeze().plan_compaction();
+      allocator.pin(&pinned).unwrap();
+      plan
+    };
+    assert_eq!(plan.relocations(), [relocation]);
+    assert_eq!(
+      allocator.commit_plan(&plan),
+      [Err(PlanError::Pinned { step: 0, from: 64 })]
+    );
+
+    allocator.unpin(&pinned).unwrap();
+    assert_eq!(allocator.validate_plan(&[relocation]), Ok(()));
+    assert!(allocator.commit_plan(&plan)[0].is_ok());
+  }
+}
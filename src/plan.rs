@@ -0,0 +1,275 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::ptr::NonNull;
+
+use crate::allocator::SpeedAllocator;
+use crate::mapping::MIN_ALLOC_SIZE;
+
+/// Move of a live allocation's `size` bytes from offset `from` to offset `to`, one step of a compaction plan.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Relocation {
+  pub from: usize,
+  pub to: usize,
+  pub size: usize,
+}
+
+/// Why [`SpeedAllocator::validate_plan`] rejected a plan, naming the offending step by its index in the plan.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PlanError {
+  /// `from` isn't the start of a live allocation.
+  UnknownAllocation { step: usize, from: usize },
+  /// `size` differs from the usable size of the allocation at `from`.
+  SizeMismatch { step: usize, size: usize, expected: usize },
+  /// The allocation at `from` is moved by an earlier step already.
+  MovedTwice { step: usize, from: usize },
+  /// The destination runs past the end of the pool.
+  OutOfPool { step: usize, end: usize },
+  /// The destination overlaps data still in place when the step runs: an allocation not moved yet, including
+  /// the source of a later step, or the destination of an earlier step.
+  Overlap { step: usize, offset: usize },
+  /// The allocation a [`CompactionPlan`] step was made for is gone and another one now starts at `from`.
+  Replaced { step: usize, from: usize },
+  /// The allocation at `from` is [pinned](SpeedAllocator::pin) and can't move.
+  Pinned { step: usize, from: usize },
+}
+
+impl fmt::Display for PlanError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PlanError::UnknownAllocation { step, from } => {
+        write!(f, "step {step}: no live allocation starts at offset {from}")
+      }
+      PlanError::SizeMismatch { step, size, expected } => {
+        write!(f, "step {step}: moves {size} bytes of an allocation of {expected}")
+      }
+      PlanError::MovedTwice { step, from } => {
+        write!(f, "step {step}: allocation at offset {from} is already moved")
+      }
+      PlanError::OutOfPool { step, end } => write!(f, "step {step}: destination ends at {end}, past the pool"),
+      PlanError::Overlap { step, offset } => {
+        write!(f, "step {step}: destination overlaps the data at offset {offset}")
+      }
+      PlanError::Replaced { step, from } => {
+        write!(f, "step {step}: the allocation at offset {from} was freed and replaced")
+      }
+      PlanError::Pinned { step, from } => write!(f, "step {step}: the allocation at offset {from} is pinned"),
+    }
+  }
+}
+
+impl std::error::Error for PlanError {}
+
+/// Compaction plan made by [`FrozenView::plan_compaction`](crate::FrozenView::plan_compaction) without holding
+/// the allocator, applied later with [`SpeedAllocator::commit_plan`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactionPlan {
+  pub(crate) generation: u64,
+  pub(crate) relocations: Vec<Relocation>,
+  // id of the allocation each step moves, to tell it apart from a later one at the same offset
+  pub(crate) alloc_ids: Vec<u64>,
+}
+
+impl CompactionPlan {
+  /// Generation of the [`FrozenView`](crate::FrozenView) the plan was made on. When the allocator is still at
+  /// this generation every step commits.
+  pub fn generation(&self) -> u64 {
+    self.generation
+  }
+
+  /// The steps, in the order they are applied.
+  pub fn relocations(&self) -> &[Relocation] {
+    &self.relocations
+  }
+}
+
+impl SpeedAllocator {
+  /// Checks that `plan` can be applied step by step without clobbering data: every step moves a whole live
+  /// allocation that isn't pinned, at most once, to a range inside the pool that overlaps no block still in place
+  /// at that point except the step's own source, which a `memmove` handles. Blocks without a live allocation,
+  /// such as quarantined or drained ones, and the alignment padding of other blocks count as in place. Moves that
+  /// swap or rotate allocations are rejected, since they can't be ordered without a scratch buffer.
+  pub fn validate_plan(&self, plan: &[Relocation]) -> Result<(), PlanError> {
+    // start -> end of every block that isn't free, padding included, never overlapping each other
+    let mut occupied: BTreeMap<usize, usize> = self
+      .blocks()
+      .filter(|block| !block.is_free)
+      .map(|block| (block.offset, block.offset + block.size))
+      .collect();
+    let mut moved = BTreeSet::new();
+    for (step, &Relocation { from, to, size }) in plan.iter().enumerate() {
+      let Ok(block) = self.indexed_block(from) else {
+        return Err(PlanError::UnknownAllocation { step, from });
+      };
+      let b = &self.blocks[block];
+      if b.pinned {
+        return Err(PlanError::Pinned { step, from });
+      }
+      let expected = b.size - b.adjustment;
+      if size != expected {
+        return Err(PlanError::SizeMismatch { step, size, expected });
+      }
+      if !moved.insert(from) {
+        return Err(PlanError::MovedTwice { step, from });
+      }
+      let end = to
+        .checked_add(size)
+        .filter(|&end| end <= self.pool.len())
+        .ok_or(PlanError::OutOfPool { step, end: to.saturating_add(size) })?;
+      // the whole source block is freed, and the destination block spans whole granules like every block
+      occupied.remove(&b.offset);
+      let (start, end) = (to - to % MIN_ALLOC_SIZE, end.next_multiple_of(MIN_ALLOC_SIZE));
+      if let Some((&offset, _)) = occupied
+        .range(..end)
+        .next_back()
+        .filter(|&(_, &other_end)| other_end > start)
+      {
+        return Err(PlanError::Overlap { step, offset });
+      }
+      occupied.insert(start, end);
+    }
+    Ok(())
+  }
+
+  /// Applies `plan` step by step, moving each allocation's data and returning its new pointer. A plan made on
+  /// an older generation is checked against the live allocator one step at a time: a step whose allocation was
+  /// freed or pinned since, or whose destination is no longer free, is rejected and skipped while the others
+  /// still apply. Moves keep the allocation's id and tag.
+  pub fn commit_plan(&mut self, plan: &CompactionPlan) -> Vec<Result<NonNull<u8>, PlanError>> {
+    let steps = plan.relocations.iter().zip(&plan.alloc_ids).enumerate();
+    steps
+      .map(|(step, (&relocation, &alloc_id))| self.commit_step(step, relocation, alloc_id))
+      .collect()
+  }
+
+  fn commit_step(
+    &mut self,
+    step: usize,
+    Relocation { from, to, size }: Relocation,
+    alloc_id: u64,
+  ) -> Result<NonNull<u8>, PlanError> {
+    let block = self
+      .indexed_block(from)
+      .map_err(|_| PlanError::UnknownAllocation { step, from })?;
+    let b = &self.blocks[block];
+    if b.alloc_id != alloc_id {
+      return Err(PlanError::Replaced { step, from });
+    }
+    if b.pinned {
+      return Err(PlanError::Pinned { step, from });
+    }
+    let expected = b.size - b.adjustment;
+    if size != expected {
+      return Err(PlanError::SizeMismatch { step, size, expected });
+    }
+    let end = to.saturating_add(size);
+    if end > self.pool.len() {
+      return Err(PlanError::OutOfPool { step, end });
+    }
+    self
+      .move_allocation(block, to)
+      .ok_or(PlanError::Overlap { step, offset: to })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn step(from: usize, to: usize, size: usize) -> Relocation {
+    Relocation { from, to, size }
+  }
+
+  // Allocations of 64 bytes at 0, 128 and 256, with free gaps in between.
+  fn spaced() -> SpeedAllocator {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptrs: Vec<_> = (0..5).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+    allocator.deallocate(ptrs[1]);
+    allocator.deallocate(ptrs[3]);
+    allocator
+  }
+
+  #[test]
+  fn sliding_plan_is_accepted() {
+    let allocator = spaced();
+    let plan = [step(128, 64, 64), step(256, 128, 64)];
+    assert_eq!(allocator.validate_plan(&plan), Ok(()));
+    // a step may overlap its own source
+    assert_eq!(allocator.validate_plan(&[step(128, 96, 64)]), Ok(()));
+  }
+
+  #[test]
+  fn every_broken_step_is_named() {
+    let allocator = spaced();
+    let cases = [
+      (
+        vec![step(64, 0, 64)],
+        PlanError::UnknownAllocation { step: 0, from: 64 },
+      ),
+      (
+        vec![step(128, 64, 32)],
+        PlanError::SizeMismatch { step: 0, size: 32, expected: 64 },
+      ),
+      (
+        vec![step(128, 64, 64), step(128, 96, 64)],
+        PlanError::MovedTwice { step: 1, from: 128 },
+      ),
+      (vec![step(128, 4064, 64)], PlanError::OutOfPool { step: 0, end: 4128 }),
+      (
+        vec![step(128, usize::MAX, 64)],
+        PlanError::OutOfPool { step: 0, end: usize::MAX },
+      ),
+      (vec![step(128, 32, 64)], PlanError::Overlap { step: 0, offset: 0 }),
+      // swapping needs a scratch buffer: the first move lands on the second's source
+      (
+        vec![step(128, 256, 64), step(256, 128, 64)],
+        PlanError::Overlap { step: 0, offset: 256 },
+      ),
+      (
+        vec![step(128, 64, 64), step(256, 96, 64)],
+        PlanError::Overlap { step: 1, offset: 64 },
+      ),
+    ];
+    for (plan, error) in cases {
+      assert_eq!(allocator.validate_plan(&plan), Err(error.clone()), "{plan:?}");
+      assert!(!error.to_string().is_empty());
+    }
+  }
+
+  #[test]
+  fn blocks_without_a_live_allocation_are_occupied() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_quarantine_depth(1);
+    let ptrs: Vec<_> = (0..3).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+    allocator.deallocate(ptrs[0]);
+    allocator.deallocate(ptrs[1]);
+    // 0 is free again, 64 is quarantined
+    let into_quarantine = [step(128, 64, 64)];
+    assert_eq!(
+      allocator.validate_plan(&into_quarantine),
+      Err(PlanError::Overlap { step: 0, offset: 64 })
+    );
+    assert_eq!(allocator.validate_plan(&[step(128, 0, 64)]), Ok(()));
+  }
+
+  #[test]
+  fn padding_of_other_blocks_is_occupied() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_alignment_origin(crate::AlignmentOrigin::PoolRelative);
+    let gap = allocator.allocate(64, 8).unwrap();
+    allocator.allocate(64, 8).unwrap();
+    let padded = allocator.allocate(64, 256).unwrap();
+    let last = allocator.allocate(64, 8).unwrap();
+    allocator.deallocate(gap);
+    assert_eq!(allocator.get_block_info(padded).unwrap().offset, 128);
+    let from = allocator.pool.offset_of(last).unwrap();
+    assert_eq!(
+      allocator.validate_plan(&[step(from, 192, 64)]),
+      Err(PlanError::Overlap { step: 0, offset: 128 })
+    );
+    // a destination off the granules takes the whole granules it touches
+    assert_eq!(
+      allocator.validate_plan(&[step(from, 4, 64)]),
+      Err(PlanError::Overlap { step: 0, offset: 64 })
+    );
+  }
+}
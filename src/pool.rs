@@ -0,0 +1,188 @@
+// Host memory handed out by the allocator. This is the only module allowed to use `unsafe`: everything else
+// works on offsets and block ids, and turns offsets into pointers through the checked methods below.
+//
+// Invariant: `base` points to a live `Box<[u8]>` of exactly `size` bytes owned by this `Pool`, leaked so that
+// pointers given to callers are never invalidated by a unique borrow of the buffer.
+use std::ptr::NonNull;
+
+pub struct Pool {
+  base: NonNull<u8>,
+  size: usize,
+}
+
+impl Pool {
+  pub fn new(size: usize) -> Self {
+    let memory = Box::into_raw(vec![0u8; size].into_boxed_slice());
+    let base = NonNull::new(memory.cast::<u8>()).expect("Box never returns a null pointer");
+    Pool { base, size }
+  }
+
+  pub fn len(&self) -> usize {
+    self.size
+  }
+
+  pub fn address(&self) -> usize {
+    self.base.as_ptr() as usize
+  }
+
+  pub fn ptr_at(&self, offset: usize) -> NonNull<u8> {
+    assert!(offset < self.size, "offset {offset} is outside the pool");
+    // SAFETY: `offset` is in bounds of the buffer, so the result stays inside the same allocation and non-null.
+    unsafe { self.base.add(offset) }
+  }
+
+  // `None` when `ptr` doesn't point into the pool.
+  pub fn offset_of(&self, ptr: NonNull<u8>) -> Option<usize> {
+    let offset = (ptr.as_ptr() as usize).wrapping_sub(self.address());
+    (offset < self.size).then_some(offset)
+  }
+
+  pub fn fill(&mut self, offset: usize, len: usize, byte: u8) {
+    self.check_range(offset, len);
+    // SAFETY: the range was checked to lie inside the buffer and `&mut self` guarantees no other access through
+    // the pool; callers only fill blocks they no longer hand out.
+    unsafe { self.base.add(offset).write_bytes(byte, len) };
+  }
+
+  // Writes `pattern` repeatedly over `len` bytes at `offset`, truncating the last repetition.
+  pub fn fill_pattern(&mut self, offset: usize, len: usize, pattern: &[u8]) {
+    self.check_range(offset, len);
+    for (i, &byte) in pattern.iter().cycle().take(len).enumerate() {
+      // SAFETY: `offset + i` is inside the buffer checked above, and `&mut self` rules out other access through
+      // the pool. Volatile so the writes really reach memory before `count_mismatches` reads them back.
+      unsafe { self.base.add(offset + i).write_volatile(byte) };
+    }
+  }
+
+  // Number of bytes in `len` bytes at `offset` that differ from `pattern` repeated.
+  pub fn count_mismatches(&self, offset: usize, len: usize, pattern: &[u8]) -> usize {
+    self.check_range(offset, len);
+    pattern
+      .iter()
+      .cycle()
+      .take(len)
+      .enumerate()
+      // SAFETY: `offset + i` is inside the buffer checked above and `u8` has no invalid bit patterns.
+      .filter(|&(i, &byte)| unsafe { self.base.add(offset + i).read_volatile() } != byte)
+      .count()
+  }
+
+  // Copies `len` bytes from offset `from` to offset `to`; the two ranges may overlap.
+  pub fn copy_within(&mut self, from: usize, to: usize, len: usize) {
+    self.check_range(from, len);
+    self.check_range(to, len);
+    // SAFETY: both ranges were checked to lie inside the buffer, `ptr::copy` allows them to overlap, and
+    // `&mut self` rules out other access through the pool.
+    unsafe { std::ptr::copy(self.base.add(from).as_ptr(), self.base.add(to).as_ptr(), len) };
+  }
+
+  fn check_range(&self, offset: usize, len: usize) {
+    assert!(
+      offset <= self.size && len <= self.size - offset,
+      "range of {len} bytes at {offset} is outside the pool"
+    );
+  }
+}
+
+// SAFETY: the pool owns its buffer uniquely, as a `Box<[u8]>` would, and frees it on drop. `NonNull` only makes
+// it `!Send` by default because it can't tell owning pointers from shared ones; moving the pool to another thread
+// moves that ownership along with it.
+unsafe impl Send for Pool {}
+
+impl Clone for Pool {
+  fn clone(&self) -> Self {
+    let pool = Pool::new(self.size);
+    // SAFETY: both buffers are live and exactly `size` bytes long, and the fresh one can't overlap ours.
+    unsafe { pool.base.copy_from_nonoverlapping(self.base, self.size) };
+    pool
+  }
+}
+
+impl Drop for Pool {
+  fn drop(&mut self) {
+    let memory = std::ptr::slice_from_raw_parts_mut(self.base.as_ptr(), self.size);
+    // SAFETY: `memory` is the slice leaked by `Pool::new` and is released exactly once, here.
+    drop(unsafe { Box::from_raw(memory) });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn pointers_and_offsets_round_trip() {
+    let pool = Pool::new(64);
+    assert_eq!(pool.ptr_at(0).as_ptr() as usize, pool.address());
+    assert_eq!(pool.offset_of(pool.ptr_at(63)), Some(63));
+    let past_end = NonNull::new((pool.address() + 64) as *mut u8).unwrap();
+    assert_eq!(pool.offset_of(past_end), None);
+    let before = NonNull::new((pool.address() - 1) as *mut u8).unwrap();
+    assert_eq!(pool.offset_of(before), None);
+  }
+
+  #[test]
+  #[should_panic(expected = "outside the pool")]
+  fn ptr_at_rejects_the_end_of_the_pool() {
+    Pool::new(64).ptr_at(64);
+  }
+
+  #[test]
+  #[should_panic(expected = "outside the pool")]
+  fn fill_rejects_ranges_past_the_end() {
+    Pool::new(64).fill(60, 8, 0);
+  }
+
+  #[test]
+  fn patterns_are_written_and_checked() {
+    let mut pool = Pool::new(64);
+    pool.fill(0, 64, 0xaa);
+    pool.fill_pattern(8, 10, &[1, 2, 3]);
+    assert_eq!(pool.count_mismatches(8, 10, &[1, 2, 3]), 0);
+    assert_eq!(pool.count_mismatches(0, 8, &[0xaa]), 0);
+    assert_eq!(pool.count_mismatches(18, 46, &[0xaa]), 0);
+    assert_eq!(pool.count_mismatches(8, 10, &[3, 2, 1]), 7);
+    pool.fill(0, 64, 0);
+    assert_eq!(pool.count_mismatches(0, 64, &[0]), 0);
+  }
+
+  #[test]
+  fn copy_within_handles_overlapping_ranges() {
+    let mut pool = Pool::new(32);
+    pool.fill_pattern(0, 16, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]);
+    pool.copy_within(0, 4, 16);
+    assert_eq!(
+      pool.count_mismatches(4, 16, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]),
+      0
+    );
+    pool.copy_within(4, 0, 16);
+    assert_eq!(
+      pool.count_mismatches(0, 16, &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15]),
+      0
+    );
+  }
+
+  #[test]
+  fn clone_copies_the_buffer_into_a_new_allocation() {
+    let mut pool = Pool::new(16);
+    pool.fill_pattern(0, 16, &[7, 9]);
+    let mut copy = pool.clone();
+    assert_ne!(copy.address(), pool.address());
+    assert_eq!(copy.count_mismatches(0, 16, &[7, 9]), 0);
+    copy.fill(0, 16, 0);
+    assert_eq!(pool.count_mismatches(0, 16, &[7, 9]), 0);
+  }
+
+  #[test]
+  fn pool_moves_to_another_thread() {
+    let mut pool = Pool::new(16);
+    pool.fill(0, 16, 5);
+    let pool = std::thread::spawn(move || {
+      assert_eq!(pool.count_mismatches(0, 16, &[5]), 0);
+      pool
+    })
+    .join()
+    .unwrap();
+    assert_eq!(pool.len(), 16);
+  }
+}
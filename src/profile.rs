@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+use crate::allocator::SpeedAllocator;
+use crate::mapping::{binmap_down, BIN_COUNT};
+
+const DEFAULT_WINDOW: usize = 1024;
+const DEFAULT_WASTE_THRESHOLD: f32 = 0.25;
+
+/// Request sizes over the recent allocation window, see [`SpeedAllocator::allocation_size_profile`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SizeProfile {
+  /// Requests per bin, indexed like [`BinRef::bin`](crate::BinRef::bin).
+  pub bin_requests: Vec<usize>,
+  pub requests: usize,
+  /// Fraction of the requests whose block held more than the waste threshold beyond the requested size.
+  pub wasteful_ratio: f32,
+}
+
+/// Total variation distance between the bin distributions of two profiles: `0.0` when requests spread over the
+/// bins in the same proportions, `1.0` when the profiles share no bin. Empty profiles are `0.0` apart from
+/// each other and `1.0` from any non-empty one.
+pub fn compare_profiles(a: &SizeProfile, b: &SizeProfile) -> f32 {
+  match (a.requests, b.requests) {
+    (0, 0) => 0.0,
+    (0, _) | (_, 0) => 1.0,
+    (total_a, total_b) => {
+      let distance: f32 = a
+        .bin_requests
+        .iter()
+        .zip(&b.bin_requests)
+        .map(|(&x, &y)| (x as f32 / total_a as f32 - y as f32 / total_b as f32).abs())
+        .sum();
+      distance / 2.0
+    }
+  }
+}
+
+// Ring buffer of the last `capacity` requests as (bin, wasteful) with running per-bin counts.
+#[derive(Clone)]
+pub(crate) struct SizeWindow {
+  samples: VecDeque<(usize, bool)>,
+  capacity: usize,
+  bin_requests: [usize; BIN_COUNT],
+  wasteful: usize,
+  waste_threshold: f32,
+}
+
+impl Default for SizeWindow {
+  fn default() -> Self {
+    SizeWindow {
+      samples: VecDeque::with_capacity(DEFAULT_WINDOW),
+      capacity: DEFAULT_WINDOW,
+      bin_requests: [0; BIN_COUNT],
+      wasteful: 0,
+      waste_threshold: DEFAULT_WASTE_THRESHOLD,
+    }
+  }
+}
+
+impl SizeWindow {
+  // `size` is the rounded request, `served` the size of the block it ended up in.
+  pub(crate) fn record(&mut self, size: usize, served: usize) {
+    if self.samples.len() == self.capacity {
+      self.forget_oldest();
+    }
+    let bin = binmap_down(size).bin();
+    let wasteful = (served - size) as f32 > size as f32 * self.waste_threshold;
+    self.bin_requests[bin] += 1;
+    self.wasteful += wasteful as usize;
+    self.samples.push_back((bin, wasteful));
+  }
+
+  fn forget_oldest(&mut self) {
+    if let Some((bin, wasteful)) = self.samples.pop_front() {
+      self.bin_requests[bin] -= 1;
+      self.wasteful -= wasteful as usize;
+    }
+  }
+}
+
+impl SpeedAllocator {
+  /// Per-bin request counts over the last allocations, by default 1024 of them, and how many of those were
+  /// served by a block more than 25% larger than the request. Comparing profiles taken over time with
+  /// [`compare_profiles`] flags workloads drifting into sizes the bins serve poorly.
+  pub fn allocation_size_profile(&self) -> SizeProfile {
+    let window = &self.size_window;
+    let requests = window.samples.len();
+    SizeProfile {
+      bin_requests: window.bin_requests.to_vec(),
+      requests,
+      wasteful_ratio: if requests == 0 {
+        0.0
+      } else {
+        window.wasteful as f32 / requests as f32
+      },
+    }
+  }
+
+  /// Number of recent allocations the size profile covers, dropping the oldest ones when shrinking.
+  ///
+  /// # Panics
+  ///
+  /// If `allocations` is zero.
+  pub fn set_size_profile_window(&mut self, allocations: usize) {
+    assert!(allocations > 0, "size profile window must hold at least one allocation");
+    let window = &mut self.size_window;
+    while window.samples.len() > allocations {
+      window.forget_oldest();
+    }
+    window.capacity = allocations;
+  }
+
+  /// Extra bytes, as a fraction of the request, above which an allocation counts as wasteful in the size
+  /// profile. Only affects allocations made from now on.
+  pub fn set_size_profile_waste_threshold(&mut self, fraction: f32) {
+    self.size_window.waste_threshold = fraction;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::allocator::FitPolicy;
+
+  #[test]
+  fn window_counts_the_latest_requests_per_bin() {
+    let mut allocator = SpeedAllocator::new(1 << 16);
+    allocator.set_size_profile_window(3);
+    for size in [64, 64, 4096, 4096] {
+      allocator.allocate(size, 8).unwrap();
+    }
+    let profile = allocator.allocation_size_profile();
+    assert_eq!(profile.requests, 3);
+    assert_eq!(profile.bin_requests[binmap_down(64).bin()], 1);
+    assert_eq!(profile.bin_requests[binmap_down(4096).bin()], 2);
+    assert_eq!(profile.wasteful_ratio, 0.0);
+
+    allocator.set_size_profile_window(1);
+    assert_eq!(allocator.allocation_size_profile().requests, 1);
+  }
+
+  #[test]
+  fn blocks_left_unsplit_count_as_wasteful() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_minimum_split_size(64);
+    allocator.set_fit_policy(FitPolicy::TightFit);
+    let hole = allocator.allocate(200, 8).unwrap();
+    allocator.allocate(4096 - 200, 8).unwrap();
+    // the 40 bytes left over are too few to split off, so the whole 200-byte hole serves 160 bytes
+    allocator.deallocate(hole);
+    let ptr = allocator.allocate(160, 8).unwrap();
+    assert_eq!(allocator.allocation_size_profile().wasteful_ratio, 0.0);
+
+    allocator.set_size_profile_waste_threshold(0.2);
+    allocator.deallocate(ptr);
+    allocator.allocate(160, 8).unwrap();
+    assert_eq!(allocator.allocation_size_profile().wasteful_ratio, 0.25);
+  }
+
+  #[test]
+  fn profile_distance() {
+    let profile =
+      |bins: &[usize]| SizeProfile { bin_requests: bins.to_vec(), requests: bins.iter().sum(), wasteful_ratio: 0.0 };
+    let empty = profile(&[0, 0]);
+    assert_eq!(compare_profiles(&empty, &empty), 0.0);
+    assert_eq!(compare_profiles(&empty, &profile(&[1, 0])), 1.0);
+    assert_eq!(compare_profiles(&profile(&[2, 2]), &profile(&[1, 1])), 0.0);
+    assert_eq!(compare_profiles(&profile(&[1, 0]), &profile(&[0, 3])), 1.0);
+    assert_eq!(compare_profiles(&profile(&[3, 1]), &profile(&[1, 1])), 0.25);
+  }
+}
@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError, Weak};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::allocator::{SpeedAllocator, Stats};
+
+// Samples a `StatsSampler` holds for its reader before dropping the oldest.
+const SAMPLE_CAPACITY: usize = 64;
+
+/// [`SpeedAllocator`] behind a mutex, to share one allocator between threads.
+pub struct LockedSpeedAllocator {
+  inner: Mutex<SpeedAllocator>,
+}
+
+impl LockedSpeedAllocator {
+  pub fn new(allocator: SpeedAllocator) -> Self {
+    LockedSpeedAllocator { inner: Mutex::new(allocator) }
+  }
+
+  /// Locks the allocator. A panic in a thread holding the lock doesn't lock the others out: they get the
+  /// allocator as that thread left it, which [`validate`](SpeedAllocator::validate) can check.
+  pub fn lock(&self) -> MutexGuard<'_, SpeedAllocator> {
+    self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+/// The counters of [`Stats`] the allocator keeps up to date, read without walking any block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct QuickStats {
+  pub used_bytes: usize,
+  pub free_bytes: usize,
+  pub free_block_count: usize,
+  pub live_allocations: usize,
+  /// See [`SpeedAllocator::generation`].
+  pub generation: u64,
+}
+
+/// One reading taken by a [`StatsSampler`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatsSample {
+  pub timestamp: Instant,
+  pub quick: QuickStats,
+  /// Taken on every `full_every`-th sample only, starting with the first.
+  pub full: Option<Stats>,
+}
+
+/// Background thread reading an allocator's stats at a fixed interval. It holds the allocator weakly and stops
+/// by itself once the allocator is dropped. Samples wait in a queue of 64; when the reader falls behind, the
+/// oldest ones are dropped.
+pub struct StatsSampler {
+  shared: Arc<Shared>,
+  thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+  state: Mutex<SamplerState>,
+  // signalled when a sample is queued or the thread exits
+  sampled: Condvar,
+  // signalled when `stop` is asked
+  stopping: Condvar,
+}
+
+#[derive(Default)]
+struct SamplerState {
+  samples: VecDeque<StatsSample>,
+  stop: bool,
+  finished: bool,
+}
+
+impl Shared {
+  fn state(&self) -> MutexGuard<'_, SamplerState> {
+    self.state.lock().unwrap_or_else(PoisonError::into_inner)
+  }
+}
+
+impl StatsSampler {
+  /// Starts sampling `allocator` every `interval`, taking the full [`Stats`] on every `full_every`-th sample
+  /// and never when `full_every` is `0`. The allocator is only locked while a sample is read.
+  pub fn spawn(allocator: Arc<LockedSpeedAllocator>, interval: Duration, full_every: usize) -> StatsSampler {
+    let shared = Arc::new(Shared {
+      state: Mutex::new(SamplerState::default()),
+      sampled: Condvar::new(),
+      stopping: Condvar::new(),
+    });
+    let allocator = Arc::downgrade(&allocator);
+    let thread = {
+      let shared = Arc::clone(&shared);
+      thread::spawn(move || sample(&allocator, &shared, interval, full_every))
+    };
+    StatsSampler { shared, thread: Some(thread) }
+  }
+
+  /// Oldest queued sample, if any.
+  pub fn try_recv(&self) -> Option<StatsSample> {
+    self.shared.state().samples.pop_front()
+  }
+
+  /// Oldest queued sample, waiting up to `timeout` for one. `None` on timeout, or right away once the sampling
+  /// thread has stopped and the queue is empty.
+  pub fn recv_timeout(&self, timeout: Duration) -> Option<StatsSample> {
+    let state = self.shared.state();
+    let (mut state, _) = self
+      .shared
+      .sampled
+      .wait_timeout_while(state, timeout, |state| state.samples.is_empty() && !state.finished)
+      .unwrap_or_else(PoisonError::into_inner);
+    state.samples.pop_front()
+  }
+
+  /// Whether the sampling thread has exited, because the allocator was dropped.
+  pub fn is_finished(&self) -> bool {
+    self.shared.state().finished
+  }
+
+  /// Stops the sampling thread and waits for it, without waiting out the current interval. Dropping the
+  /// sampler does the same.
+  pub fn stop(self) {}
+}
+
+impl Drop for StatsSampler {
+  fn drop(&mut self) {
+    self.shared.state().stop = true;
+    self.shared.stopping.notify_all();
+    if let Some(thread) = self.thread.take() {
+      let _ = thread.join();
+    }
+  }
+}
+
+fn sample(allocator: &Weak<LockedSpeedAllocator>, shared: &Shared, interval: Duration, full_every: usize) {
+  let mut taken = 0;
+  while let Some(allocator) = allocator.upgrade() {
+    let sample = {
+      let allocator = allocator.lock();
+      StatsSample {
+        timestamp: Instant::now(),
+        quick: allocator.quick_stats(),
+        full: (full_every != 0 && taken % full_every == 0).then(|| allocator.stats()),
+      }
+    };
+    drop(allocator);
+    taken += 1;
+    let mut state = shared.state();
+    if state.samples.len() == SAMPLE_CAPACITY {
+      state.samples.pop_front();
+    }
+    state.samples.push_back(sample);
+    shared.sampled.notify_all();
+    let (state, _) = shared
+      .stopping
+      .wait_timeout_while(state, interval, |state| !state.stop)
+      .unwrap_or_else(PoisonError::into_inner);
+    if state.stop {
+      break;
+    }
+  }
+  shared.state().finished = true;
+  shared.sampled.notify_all();
+}
+
+impl SpeedAllocator {
+  /// The counters of [`stats`](Self::stats) that cost nothing to read.
+  pub fn quick_stats(&self) -> QuickStats {
+    QuickStats {
+      used_bytes: self.pool.len() - self.free_bytes,
+      free_bytes: self.free_bytes,
+      free_block_count: self.free_block_count,
+      live_allocations: self.allocations.len(),
+      generation: self.generation,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn shared_allocator() -> Arc<LockedSpeedAllocator> {
+    Arc::new(LockedSpeedAllocator::new(SpeedAllocator::new(4096)))
+  }
+
+  #[test]
+  fn samples_arrive_with_full_stats_at_the_configured_cadence() {
+    let allocator = shared_allocator();
+    allocator.lock().allocate(64, 8).unwrap();
+    let sampler = StatsSampler::spawn(Arc::clone(&allocator), Duration::from_millis(5), 3);
+    let samples: Vec<_> = (0..7)
+      .map(|_| sampler.recv_timeout(Duration::from_secs(10)).unwrap())
+      .collect();
+    sampler.stop();
+    let full: Vec<_> = samples.iter().map(|sample| sample.full.is_some()).collect();
+    assert_eq!(full, [true, false, false, true, false, false, true]);
+    assert_eq!(samples[0].quick.live_allocations, 1);
+    assert_eq!(samples[0].full.unwrap().used_bytes, samples[0].quick.used_bytes);
+    assert!(samples.windows(2).all(|pair| pair[0].timestamp <= pair[1].timestamp));
+  }
+
+  #[test]
+  fn stop_does_not_wait_out_the_interval() {
+    let sampler = StatsSampler::spawn(shared_allocator(), Duration::from_secs(3600), 1);
+    let started = Instant::now();
+    sampler.stop();
+    assert!(started.elapsed() < Duration::from_secs(60));
+  }
+
+  #[test]
+  fn sampling_ends_when_the_allocator_is_dropped() {
+    let allocator = shared_allocator();
+    let sampler = StatsSampler::spawn(Arc::clone(&allocator), Duration::from_millis(1), 0);
+    assert!(sampler.recv_timeout(Duration::from_secs(10)).unwrap().full.is_none());
+    drop(allocator);
+    while sampler.recv_timeout(Duration::from_secs(10)).is_some() {}
+    assert!(sampler.is_finished());
+  }
+}
@@ -0,0 +1,189 @@
+use std::fmt;
+use std::ptr::NonNull;
+
+use crate::allocator::SpeedAllocator;
+use crate::error::AllocError;
+use crate::mapping::{LINEAR, MIN_ALLOC_SIZE};
+use crate::validate::ValidationError;
+
+// Pool of the scratch allocator `self_check` runs its workload on.
+const SELF_CHECK_POOL: usize = 64 * 1024;
+
+/// Step of [`SpeedAllocator::self_check`] that went wrong, and how.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SelfCheckError {
+  pub step: &'static str,
+  pub failure: SelfCheckFailure,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelfCheckFailure {
+  /// A request the scratch pool has room for failed.
+  AllocationFailed { size: usize, alignment: usize },
+  /// An allocation doesn't honour its alignment.
+  Misaligned { offset: usize, alignment: usize },
+  /// Freeing a live allocation failed.
+  DeallocationFailed(AllocError),
+  /// The free blocks don't add up to the expected layout.
+  UnexpectedLayout { free_blocks: usize, expected: usize },
+  /// [`SpeedAllocator::validate`] found a broken invariant.
+  Invariant(ValidationError),
+}
+
+impl fmt::Display for SelfCheckError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "self check failed at step {:?}: ", self.step)?;
+    match &self.failure {
+      SelfCheckFailure::AllocationFailed { size, alignment } => {
+        write!(f, "{size} bytes aligned to {alignment} could not be allocated")
+      }
+      SelfCheckFailure::Misaligned { offset, alignment } => {
+        write!(f, "allocation at offset {offset} isn't aligned to {alignment}")
+      }
+      SelfCheckFailure::DeallocationFailed(error) => write!(f, "{error}"),
+      SelfCheckFailure::UnexpectedLayout { free_blocks, expected } => {
+        write!(f, "{free_blocks} free blocks, expected {expected}")
+      }
+      SelfCheckFailure::Invariant(error) => write!(f, "{error}"),
+    }
+  }
+}
+
+impl std::error::Error for SelfCheckError {}
+
+impl SpeedAllocator {
+  /// Smoke test for the allocator code as compiled, meant to run once at startup to catch miscompiles on
+  /// unusual targets. A fixed workload covering splits, merges, alignment, size class boundaries and growing in
+  /// place runs on a scratch allocator of 64 KiB set up with this allocator's fit policy and poison byte,
+  /// validating after every step. This allocator itself is left untouched.
+  pub fn self_check(&self) -> Result<(), SelfCheckError> {
+    let mut scratch = SpeedAllocator::new(SELF_CHECK_POOL);
+    scratch.set_fit_policy(self.fit_policy);
+    scratch.set_poison_on_free(self.poison_on_free);
+
+    let mut step = "split";
+    let mut live = Vec::new();
+    for size in [64, 24, 200] {
+      live.push(scratch.checked_allocate(step, size, MIN_ALLOC_SIZE)?);
+      scratch.check_step(step)?;
+    }
+    scratch.expect_free_blocks(step, 1)?;
+
+    step = "alignment";
+    for shift in 3..=12 {
+      live.push(scratch.checked_allocate(step, 40, 1 << shift)?);
+      scratch.check_step(step)?;
+    }
+
+    step = "bin boundaries";
+    let linear = 1 << LINEAR;
+    for size in [
+      linear - MIN_ALLOC_SIZE,
+      linear,
+      linear + MIN_ALLOC_SIZE,
+      2 * linear - 1,
+      2 * linear,
+    ] {
+      live.push(scratch.checked_allocate(step, size, MIN_ALLOC_SIZE)?);
+      scratch.check_step(step)?;
+    }
+
+    step = "merge";
+    for ptr in live.iter().skip(1).step_by(2).chain(live.iter().step_by(2)) {
+      scratch
+        .try_deallocate(*ptr)
+        .map_err(|error| SelfCheckError { step, failure: SelfCheckFailure::DeallocationFailed(error) })?;
+      scratch.check_step(step)?;
+    }
+    scratch.expect_free_blocks(step, 1)?;
+
+    step = "grow in place";
+    let first = scratch.checked_allocate(step, 128, MIN_ALLOC_SIZE)?;
+    let second = scratch.checked_allocate(step, 128, MIN_ALLOC_SIZE)?;
+    let third = scratch.checked_allocate(step, 128, MIN_ALLOC_SIZE)?;
+    scratch.deallocate(second);
+    scratch.check_step(step)?;
+    scratch
+      .allocate_after(first, 128, MIN_ALLOC_SIZE)
+      .ok_or(SelfCheckError {
+        step,
+        failure: SelfCheckFailure::AllocationFailed { size: 128, alignment: MIN_ALLOC_SIZE },
+      })?;
+    scratch.check_step(step)?;
+    scratch.expect_free_blocks(step, 1)?;
+    scratch.deallocate(third);
+    scratch.check_step(step)?;
+    Ok(())
+  }
+
+  fn checked_allocate(
+    &mut self,
+    step: &'static str,
+    size: usize,
+    alignment: usize,
+  ) -> Result<NonNull<u8>, SelfCheckError> {
+    let failure = match self.allocate(size, alignment) {
+      Some(ptr) if (ptr.as_ptr() as usize).is_multiple_of(alignment) => return Ok(ptr),
+      Some(ptr) => SelfCheckFailure::Misaligned { offset: self.pool.offset_of(ptr).unwrap_or(0), alignment },
+      None => SelfCheckFailure::AllocationFailed { size, alignment },
+    };
+    Err(SelfCheckError { step, failure })
+  }
+
+  fn check_step(&self, step: &'static str) -> Result<(), SelfCheckError> {
+    self
+      .validate()
+      .map_err(|error| SelfCheckError { step, failure: SelfCheckFailure::Invariant(error) })
+  }
+
+  fn expect_free_blocks(&self, step: &'static str, expected: usize) -> Result<(), SelfCheckError> {
+    if self.free_block_count == expected {
+      Ok(())
+    } else {
+      Err(SelfCheckError {
+        step,
+        failure: SelfCheckFailure::UnexpectedLayout { free_blocks: self.free_block_count, expected },
+      })
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::allocator::FitPolicy;
+
+  #[test]
+  fn self_check_passes_under_every_policy_and_leaves_the_allocator_alone() {
+    for policy in [FitPolicy::GoodFit, FitPolicy::TightFit, FitPolicy::ExactFitFirst] {
+      let mut allocator = SpeedAllocator::new(4096);
+      allocator.set_fit_policy(policy);
+      allocator.set_poison_on_free(Some(0xdd));
+      allocator.allocate(64, 8).unwrap();
+      let (state, generation) = (allocator.dump_state_to_vec(), allocator.generation());
+      assert_eq!(allocator.self_check(), Ok(()), "{policy:?}");
+      assert_eq!(allocator.dump_state_to_vec(), state);
+      assert_eq!(allocator.generation(), generation);
+    }
+  }
+
+  #[test]
+  fn failures_name_their_step() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.allocate(64, 8).unwrap();
+    let error = allocator.expect_free_blocks("merge", 2).unwrap_err();
+    assert_eq!(
+      error,
+      SelfCheckError { step: "merge", failure: SelfCheckFailure::UnexpectedLayout { free_blocks: 1, expected: 2 } }
+    );
+    assert_eq!(
+      error.to_string(),
+      "self check failed at step \"merge\": 1 free blocks, expected 2"
+    );
+    let error = allocator.checked_allocate("split", 8192, 8).unwrap_err();
+    assert_eq!(
+      error.failure,
+      SelfCheckFailure::AllocationFailed { size: 8192, alignment: 8 }
+    );
+  }
+}
@@ -0,0 +1,329 @@
+use std::fmt;
+
+use crate::allocator::SpeedAllocator;
+use crate::bitmap::Bitmap;
+use crate::block::Block;
+use crate::mapping::{binmap_down, BIN_COUNT, MIN_ALLOC_SIZE, SUB_BIN};
+use crate::validate::ValidationError;
+
+const MAGIC: &[u8; 8] = b"SPDALLOC";
+const VERSION: u32 = 1;
+
+const FREE: u32 = 1;
+const DRAINED: u32 = 1 << 1;
+const FENCED: u32 = 1 << 2;
+const QUARANTINED: u32 = 1 << 3;
+
+// The sub-bin bitmaps are stored as 4 bytes each.
+const _: () = assert!(SUB_BIN <= u32::BITS as usize);
+
+/// Why [`SpeedAllocator::restore_from_vec`] rejected a dump.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RestoreError {
+  /// The dump ends before the layout it announces.
+  Truncated,
+  /// Bytes left over after the last block.
+  TrailingBytes,
+  BadMagic,
+  UnsupportedVersion {
+    version: u32,
+  },
+  /// A pool size below 8 bytes or not a multiple of 8.
+  InvalidPoolSize {
+    size: usize,
+  },
+  /// A value that doesn't fit in `usize` on this target.
+  ValueTooLarge,
+  /// A block record no allocator could have written: empty, misaligned, not starting where the previous block
+  /// ends, or with impossible flags or padding.
+  InvalidBlock {
+    index: usize,
+  },
+  /// The blocks end at `offset`, short of the pool end.
+  Uncovered {
+    offset: usize,
+  },
+  /// The stored bitmaps disagree with the free blocks of the chain.
+  BitmapMismatch,
+  /// The rebuilt allocator breaks an invariant.
+  Invalid(ValidationError),
+}
+
+impl fmt::Display for RestoreError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      RestoreError::Truncated => write!(f, "allocator dump is truncated"),
+      RestoreError::TrailingBytes => write!(f, "allocator dump has trailing bytes"),
+      RestoreError::BadMagic => write!(f, "not an allocator dump"),
+      RestoreError::UnsupportedVersion { version } => write!(f, "unsupported allocator dump version {version}"),
+      RestoreError::InvalidPoolSize { size } => write!(f, "dumped pool size {size} is invalid"),
+      RestoreError::ValueTooLarge => write!(f, "allocator dump holds a value too large for this target"),
+      RestoreError::InvalidBlock { index } => write!(f, "block {index} of the dump is invalid"),
+      RestoreError::Uncovered { offset } => write!(f, "dumped blocks leave the pool uncovered from offset {offset}"),
+      RestoreError::BitmapMismatch => write!(f, "dumped bitmaps don't match the free blocks"),
+      RestoreError::Invalid(error) => write!(f, "restored allocator is inconsistent: {error}"),
+    }
+  }
+}
+
+impl std::error::Error for RestoreError {}
+
+impl SpeedAllocator {
+  /// Serializes the block layout, little-endian:
+  ///
+  /// - 8-byte magic `SPDALLOC`, 4-byte version (1), 8-byte pool size
+  /// - 8-byte bin bitmap, then one 4-byte sub-bin bitmap per bin (57 on 64-bit targets)
+  /// - 8-byte block count, then per block in offset order its 8-byte offset, 8-byte size, 8-byte alignment
+  ///   adjustment and 4-byte flags (1 free, 2 drained, 4 fenced, 8 quarantined)
+  ///
+  /// The pool contents, settings, counters, tags and pins are not part of the dump. Quarantined blocks are
+  /// recorded, but with the quarantine depth back at its default of `0` a restored allocator releases them into
+  /// the free lists, merged with their free neighbours. Adjustments are kept as they are, so
+  /// with [`AlignmentOrigin::Host`](crate::AlignmentOrigin::Host) a restored allocator only keeps the alignment of
+  /// its live allocations if its pool lands at an equally aligned address.
+  pub fn dump_state_to_vec(&self) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&(self.pool.len() as u64).to_le_bytes());
+    out.extend_from_slice(&self.bin_bitmap.bits().to_le_bytes());
+    for bitmap in &self.sub_bin_bitmap {
+      out.extend_from_slice(&(bitmap.bits() as u32).to_le_bytes());
+    }
+    out.extend_from_slice(&(self.blocks().count() as u64).to_le_bytes());
+    let mut cursor = Some(self.head);
+    while let Some(id) = cursor {
+      let block = &self.blocks[id];
+      let flags = (block.is_free as u32 * FREE)
+        | (block.drained as u32 * DRAINED)
+        | (block.fenced as u32 * FENCED)
+        | (block.quarantined as u32 * QUARANTINED);
+      for field in [block.offset, block.size, block.adjustment] {
+        out.extend_from_slice(&(field as u64).to_le_bytes());
+      }
+      out.extend_from_slice(&flags.to_le_bytes());
+      cursor = block.next_physical;
+    }
+    out
+  }
+
+  /// Rebuilds an allocator from [`dump_state_to_vec`](Self::dump_state_to_vec) over a fresh zeroed pool,
+  /// relinking every block and refilling the free lists, then runs [`validate`](Self::validate). The whole dump
+  /// is parsed, and the blocks checked to cover the pool and to match the stored bitmaps, before the pool is
+  /// allocated. Settings start at their defaults, so quarantined blocks are freed,
+  /// and allocation ids are handed out again in offset order.
+  pub fn restore_from_vec(bytes: &[u8]) -> Result<SpeedAllocator, RestoreError> {
+    let mut input = Reader(bytes);
+    if input.take(MAGIC.len())? != MAGIC {
+      return Err(RestoreError::BadMagic);
+    }
+    let version = input.u32()?;
+    if version != VERSION {
+      return Err(RestoreError::UnsupportedVersion { version });
+    }
+    let pool_size = input.usize()?;
+    if pool_size < MIN_ALLOC_SIZE || pool_size % MIN_ALLOC_SIZE != 0 {
+      return Err(RestoreError::InvalidPoolSize { size: pool_size });
+    }
+    let bin_bitmap = Bitmap::from_bits(input.u64()?);
+    let mut sub_bin_bitmap = [Bitmap::default(); BIN_COUNT];
+    for bitmap in &mut sub_bin_bitmap {
+      *bitmap = Bitmap::from_bits(input.u32()? as u64);
+    }
+
+    let count = input.usize()?;
+    let mut blocks = Vec::new();
+    // end of the last block read
+    let mut covered = 0;
+    for index in 0..count {
+      let (offset, size, adjustment, flags) = (input.usize()?, input.usize()?, input.usize()?, input.u32()?);
+      let is_free = flags & FREE != 0;
+      // at most one of free, drained and quarantined; only a live allocation has alignment padding
+      let state = flags & (FREE | DRAINED | QUARANTINED);
+      let valid = size > 0
+        && offset == covered
+        && offset.checked_add(size).is_some_and(|end| end <= pool_size)
+        && size % MIN_ALLOC_SIZE == 0
+        && flags & !(FREE | DRAINED | FENCED | QUARANTINED) == 0
+        && state.count_ones() <= 1
+        && if state == 0 { adjustment < size } else { adjustment == 0 };
+      if !valid {
+        return Err(RestoreError::InvalidBlock { index });
+      }
+      covered = offset + size;
+      blocks.push(Block {
+        adjustment,
+        is_free,
+        drained: flags & DRAINED != 0,
+        fenced: flags & FENCED != 0,
+        quarantined: flags & QUARANTINED != 0,
+        ..Block::new(offset, size)
+      });
+    }
+    if covered != pool_size {
+      return Err(RestoreError::Uncovered { offset: covered });
+    }
+    if !input.0.is_empty() {
+      return Err(RestoreError::TrailingBytes);
+    }
+    let mut free_bin_bitmap = Bitmap::default();
+    let mut free_sub_bin_bitmap = [Bitmap::default(); BIN_COUNT];
+    for block in blocks.iter().filter(|block| block.is_free) {
+      let map = binmap_down(block.size);
+      free_bin_bitmap.set(map.bin());
+      free_sub_bin_bitmap[map.bin()].set(map.sub_bin());
+    }
+    if free_bin_bitmap != bin_bitmap || free_sub_bin_bitmap != sub_bin_bitmap {
+      return Err(RestoreError::BitmapMismatch);
+    }
+
+    let mut restored =
+      SpeedAllocator::try_new(pool_size).map_err(|_| RestoreError::InvalidPoolSize { size: pool_size })?;
+    restored.rebuild_layout(blocks);
+    restored.node_allocations = count as u64;
+    restored.node_frees = 0;
+    // the depth isn't dumped, and at the default of 0 nothing would ever push these out
+    restored.set_quarantine_depth(0);
+    restored.generation = 0;
+    restored.validate().map_err(RestoreError::Invalid)?;
+    Ok(restored)
+  }
+}
+
+// Little-endian cursor over a dump.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+  fn take(&mut self, len: usize) -> Result<&'a [u8], RestoreError> {
+    if self.0.len() < len {
+      return Err(RestoreError::Truncated);
+    }
+    let (head, rest) = self.0.split_at(len);
+    self.0 = rest;
+    Ok(head)
+  }
+
+  fn u32(&mut self) -> Result<u32, RestoreError> {
+    Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("took 4 bytes")))
+  }
+
+  fn u64(&mut self) -> Result<u64, RestoreError> {
+    Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("took 8 bytes")))
+  }
+
+  fn usize(&mut self) -> Result<usize, RestoreError> {
+    usize::try_from(self.u64()?).map_err(|_| RestoreError::ValueTooLarge)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // Offset, size, free flag and padding of every block.
+  fn layout(allocator: &SpeedAllocator) -> Vec<(usize, usize, bool, usize)> {
+    allocator
+      .blocks()
+      .map(|block| (block.offset, block.size, block.is_free, block.alignment_waste))
+      .collect()
+  }
+
+  fn populated() -> SpeedAllocator {
+    let mut allocator = SpeedAllocator::new(8192);
+    allocator.set_alignment_origin(crate::AlignmentOrigin::PoolRelative);
+    allocator.set_quarantine_depth(1);
+    let ptrs: Vec<_> = [(100, 8), (40, 64), (300, 8), (8, 8)]
+      .iter()
+      .map(|&(size, alignment)| allocator.allocate(size, alignment).unwrap())
+      .collect();
+    allocator.deallocate(ptrs[0]);
+    allocator.deallocate(ptrs[2]);
+    allocator.split_at_power_of_two_boundaries(2048);
+    allocator
+  }
+
+  #[test]
+  fn dump_and_restore_round_trip() {
+    let mut allocator = populated();
+    for drain in [false, true] {
+      if drain {
+        allocator.drain_free_blocks(|_, _| {});
+      }
+      let dump = allocator.dump_state_to_vec();
+      let mut restored = SpeedAllocator::restore_from_vec(&dump).unwrap();
+      let mut released = allocator.clone();
+      released.set_quarantine_depth(0);
+      assert_eq!(layout(&restored), layout(&released));
+      assert_eq!(restored.quarantined_blocks(), 0);
+      assert_eq!(restored.stats().live_allocations, allocator.stats().live_allocations);
+      let again = SpeedAllocator::restore_from_vec(&restored.dump_state_to_vec()).unwrap();
+      assert_eq!(again.dump_state_to_vec(), restored.dump_state_to_vec());
+
+      // the block quarantined at the time of the dump can be allocated again
+      assert_eq!(allocator.quarantined_blocks(), 1);
+      let quarantined = &allocator.blocks[allocator.quarantine[0]];
+      assert_eq!(
+        restored.stats().free_bytes,
+        allocator.stats().free_bytes + quarantined.size
+      );
+      restored.set_fit_policy(crate::FitPolicy::TightFit);
+      let ptr = restored.allocate(quarantined.size, 8).unwrap();
+      assert_eq!(restored.pool.offset_of(ptr), Some(quarantined.offset));
+    }
+  }
+
+  #[test]
+  fn damaged_dumps_are_rejected() {
+    let dump = populated().dump_state_to_vec();
+    let restore = |edit: &dyn Fn(&mut Vec<u8>)| {
+      let mut bytes = dump.clone();
+      edit(&mut bytes);
+      SpeedAllocator::restore_from_vec(&bytes).err()
+    };
+    // header: magic, version, pool size, bin bitmap, sub-bin bitmaps, block count
+    let blocks_at = 8 + 4 + 8 + 8 + BIN_COUNT * 4 + 8;
+    assert_eq!(restore(&|bytes| bytes[0] = b'X'), Some(RestoreError::BadMagic));
+    assert_eq!(
+      restore(&|bytes| bytes[8] = 2),
+      Some(RestoreError::UnsupportedVersion { version: 2 })
+    );
+    assert_eq!(
+      restore(&|bytes| bytes[12] = 7),
+      Some(RestoreError::InvalidPoolSize { size: 8192 + 7 })
+    );
+    assert_eq!(
+      restore(&|bytes| bytes.truncate(bytes.len() - 1)),
+      Some(RestoreError::Truncated)
+    );
+    assert_eq!(restore(&|bytes| bytes.push(0)), Some(RestoreError::TrailingBytes));
+    assert_eq!(restore(&|bytes| bytes[20] ^= 1), Some(RestoreError::BitmapMismatch));
+    // first block: free and quarantined at once
+    assert_eq!(
+      restore(&|bytes| bytes[blocks_at + 24] = (FREE | QUARANTINED) as u8),
+      Some(RestoreError::InvalidBlock { index: 0 })
+    );
+    // second block, a used one: size grown over its neighbour
+    assert_eq!(
+      restore(&|bytes| bytes[blocks_at + 28 + 8] += 8),
+      Some(RestoreError::InvalidBlock { index: 2 })
+    );
+    // a pool of 64 TiB is never allocated for a dump that doesn't describe it
+    let huge = |bytes: &mut Vec<u8>| bytes[12..20].copy_from_slice(&(1u64 << 46).to_le_bytes());
+    assert_eq!(
+      restore(&|bytes| {
+        huge(bytes);
+        bytes.truncate(blocks_at);
+      }),
+      Some(RestoreError::Truncated)
+    );
+    assert_eq!(
+      restore(&|bytes| {
+        huge(bytes);
+        bytes.truncate(blocks_at - 8);
+        bytes.extend_from_slice(&0u64.to_le_bytes());
+      }),
+      Some(RestoreError::Uncovered { offset: 0 })
+    );
+    assert_eq!(restore(&huge), Some(RestoreError::Uncovered { offset: 8192 }));
+  }
+}
@@ -0,0 +1,102 @@
+use std::collections::btree_map::Entry;
+use std::ptr::NonNull;
+
+use crate::allocator::SpeedAllocator;
+use crate::block::BlockId;
+
+impl SpeedAllocator {
+  /// Allocates like [`allocate`](Self::allocate) and records `tag` for the allocation, e.g. the frame or
+  /// subsystem that owns it.
+  pub fn allocate_tagged(&mut self, size: usize, alignment: usize, tag: u64) -> Option<NonNull<u8>> {
+    let ptr = self.allocate(size, alignment)?;
+    let block = self.allocation_block(ptr).ok()?;
+    self.set_tag(block, Some(tag));
+    Some(ptr)
+  }
+
+  pub fn tag_of(&self, ptr: NonNull<u8>) -> Option<u64> {
+    let block = self.allocation_block(ptr).ok()?;
+    self.blocks[block].tag
+  }
+
+  /// Frees every live allocation tagged `tag` and returns the number of bytes released. The allocations are
+  /// looked up in an index of tags instead of a scan of every live allocation. Neighbouring blocks are merged in
+  /// a single pass at the end instead of after each free.
+  pub fn dealloc_all_tagged(&mut self, tag: u64) -> usize {
+    if self.poisoned.get() {
+      return 0;
+    }
+    let offsets = self.tagged.remove(&tag).unwrap_or_default();
+    let mut freed = 0;
+    for offset in offsets {
+      if let Ok(block) = self.indexed_block(offset) {
+        self.allocations.remove(&offset);
+        freed += self.blocks[block].size;
+        self.mark_free(block);
+        // whatever leaves the quarantine is picked up by `coalesce_all` below
+        self.quarantine(block);
+      }
+    }
+    if freed > 0 {
+      self.coalesce_all();
+      self.generation += 1;
+      #[cfg(feature = "time-series")]
+      self.record_utilization();
+    }
+    freed
+  }
+
+  // Sets the tag of the allocation in `block`, moving its offset between the entries of the tag index.
+  pub(crate) fn set_tag(&mut self, block: BlockId, tag: Option<u64>) {
+    let b = &mut self.blocks[block];
+    let offset = b.offset + b.adjustment;
+    if let Some(old) = std::mem::replace(&mut b.tag, tag) {
+      if let Entry::Occupied(mut offsets) = self.tagged.entry(old) {
+        offsets.get_mut().remove(&offset);
+        if offsets.get().is_empty() {
+          offsets.remove();
+        }
+      }
+    }
+    if let Some(tag) = tag {
+      self.tagged.entry(tag).or_default().insert(offset);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::BTreeSet;
+
+  use super::*;
+  use crate::AlignmentOrigin;
+
+  #[test]
+  fn tag_index_follows_frees_moves_and_resets() {
+    let mut allocator = SpeedAllocator::new(4096);
+    // compaction keeps the offsets as aligned as they are, so only the one at 64 moves
+    allocator.set_alignment_origin(AlignmentOrigin::PoolRelative);
+    let gap = allocator.allocate_tagged(64, 8, 1).unwrap();
+    allocator.allocate_tagged(64, 8, 2).unwrap();
+    allocator.allocate_tagged(32, 8, 2).unwrap();
+    allocator.allocate_tagged(32, 8, 3).unwrap();
+    allocator.deallocate(gap);
+    assert!(!allocator.tagged.contains_key(&1));
+
+    let plan = allocator.freeze().plan_compaction();
+    assert_eq!(plan.relocations().len(), 1);
+    let moved = allocator.commit_plan(&plan)[0].clone().unwrap();
+    assert_eq!(allocator.tag_of(moved), Some(2));
+    assert_eq!(allocator.tagged[&2], BTreeSet::from([0, 128]));
+    assert_eq!(allocator.tagged[&3], BTreeSet::from([160]));
+    assert_eq!(allocator.dealloc_all_tagged(2), 96);
+    assert_eq!(allocator.tag_of(moved), None);
+    assert_eq!(allocator.dealloc_all_tagged(2), 0);
+    assert_eq!(allocator.stats().live_allocations, 1);
+    allocator.validate().unwrap();
+
+    allocator.reset();
+    assert!(allocator.tagged.is_empty());
+    assert_eq!(allocator.dealloc_all_tagged(3), 0);
+  }
+}
@@ -0,0 +1,94 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::allocator::SpeedAllocator;
+
+// Samples kept by a new allocator, about 100 KiB of history.
+const DEFAULT_CAPACITY: usize = 4096;
+
+// Utilization after every allocation and free, oldest first, holding at most `capacity` samples.
+#[derive(Clone)]
+pub(crate) struct UtilizationSeries {
+  samples: VecDeque<(Instant, f64)>,
+  capacity: usize,
+}
+
+impl Default for UtilizationSeries {
+  fn default() -> Self {
+    UtilizationSeries { samples: VecDeque::with_capacity(DEFAULT_CAPACITY), capacity: DEFAULT_CAPACITY }
+  }
+}
+
+impl SpeedAllocator {
+  /// Percentage of the pool in use, as in [`Stats::used_bytes`](crate::Stats::used_bytes), sampled after every
+  /// allocation and every free call, oldest first. A batch or tag-wide free counts as one call. Only the latest
+  /// [`set_utilization_history`](Self::set_utilization_history) samples are kept, 4096 by default; older ones
+  /// are overwritten.
+  pub fn utilization_over_time(&self) -> Vec<(Instant, f64)> {
+    self.utilization.samples.iter().copied().collect()
+  }
+
+  /// The samples of [`utilization_over_time`](Self::utilization_over_time) taken in the last `duration`.
+  pub fn utilization_over_time_windowed(&self, duration: Duration) -> Vec<(Instant, f64)> {
+    let samples = &self.utilization.samples;
+    let start = match Instant::now().checked_sub(duration) {
+      Some(since) => samples.partition_point(|&(at, _)| at < since),
+      None => 0,
+    };
+    samples.range(start..).copied().collect()
+  }
+
+  /// Number of samples [`utilization_over_time`](Self::utilization_over_time) keeps, dropping the oldest ones
+  /// beyond it right away. `0` stops sampling.
+  pub fn set_utilization_history(&mut self, capacity: usize) {
+    let series = &mut self.utilization;
+    let excess = series.samples.len().saturating_sub(capacity);
+    series.samples.drain(..excess);
+    series.samples.shrink_to(capacity);
+    series.capacity = capacity;
+  }
+
+  pub(crate) fn record_utilization(&mut self) {
+    let pool_size = self.pool.len();
+    let percent = (pool_size - self.free_bytes) as f64 / pool_size as f64 * 100.0;
+    let series = &mut self.utilization;
+    if series.capacity == 0 {
+      return;
+    }
+    if series.samples.len() == series.capacity {
+      series.samples.pop_front();
+    }
+    series.samples.push_back((Instant::now(), percent));
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn series_keeps_the_latest_samples_up_to_its_capacity() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.set_utilization_history(3);
+    let ptrs: Vec<_> = (0..4).map(|_| allocator.allocate(512, 8).unwrap()).collect();
+    let percents: Vec<_> = allocator
+      .utilization_over_time()
+      .iter()
+      .map(|&(_, percent)| percent)
+      .collect();
+    assert_eq!(percents, [25.0, 37.5, 50.0]);
+
+    allocator.set_utilization_history(1);
+    allocator.deallocate(ptrs[3]);
+    let percents: Vec<_> = allocator
+      .utilization_over_time()
+      .iter()
+      .map(|&(_, percent)| percent)
+      .collect();
+    assert_eq!(percents, [37.5]);
+
+    allocator.set_utilization_history(0);
+    allocator.deallocate(ptrs[2]);
+    assert!(allocator.utilization_over_time().is_empty());
+  }
+}
@@ -0,0 +1,385 @@
+use std::fmt;
+
+use crate::allocator::SpeedAllocator;
+use crate::block::{Block, BlockId};
+use crate::mapping::{binmap_down, BinRef, BLOCK_COUNT};
+
+/// A broken allocator invariant, as found by [`SpeedAllocator::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+  /// The block doesn't start where the previous one ends.
+  Gap { offset: usize, expected_offset: usize },
+  /// The next block's `prev_physical` doesn't point back at this block.
+  BrokenPhysicalLink { offset: usize },
+  /// Two neighbouring free blocks that should have been merged.
+  UnmergedFreeBlocks { offset: usize },
+  /// A used block missing from the offset index.
+  UnindexedAllocation { offset: usize },
+  /// An offset index entry pointing at a block that isn't a live allocation starting there.
+  StaleIndexEntry { offset: usize },
+  /// A free list links a used block, or a block whose `prev_free` doesn't match the list.
+  BrokenFreeList { bin: BinRef, offset: usize },
+  /// A free list whose `next_free` chain loops back on itself.
+  FreeListCycle { bin: BinRef },
+  /// The bitmap bits of a size class say it has free blocks when its free list is empty, or the other way
+  /// round.
+  BitmapMismatch { bin: BinRef },
+  MisplacedFreeBlock {
+    offset: usize,
+    size: usize,
+    bin: BinRef,
+    expected_bin: BinRef,
+  },
+  /// The blocks don't cover the pool exactly.
+  SizeMismatch { found: usize, expected: usize },
+  /// A counter disagrees with what walking the blocks found.
+  CounterMismatch {
+    counter: &'static str,
+    found: usize,
+    expected: usize,
+  },
+}
+
+impl fmt::Display for ValidationError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ValidationError::Gap { offset, expected_offset } => {
+        write!(f, "block at offset {offset} should start at {expected_offset}")
+      }
+      ValidationError::BrokenPhysicalLink { offset } => {
+        write!(f, "block after offset {offset} doesn't link back to it")
+      }
+      ValidationError::UnmergedFreeBlocks { offset } => {
+        write!(f, "free block at offset {offset} has a free next neighbour")
+      }
+      ValidationError::UnindexedAllocation { offset } => {
+        write!(f, "used block at offset {offset} is missing from the allocation index")
+      }
+      ValidationError::StaleIndexEntry { offset } => {
+        write!(f, "allocation index entry for offset {offset} doesn't match its block")
+      }
+      ValidationError::BrokenFreeList { bin, offset } => {
+        write!(f, "free list of {bin} is broken at offset {offset}")
+      }
+      ValidationError::FreeListCycle { bin } => write!(f, "free list of {bin} loops back on itself"),
+      ValidationError::BitmapMismatch { bin } => write!(f, "bitmaps disagree with the free list of {bin}"),
+      ValidationError::MisplacedFreeBlock { offset, size, bin, expected_bin } => {
+        write!(
+          f,
+          "free block at offset {offset} (size {size}) is in {bin}, expected {expected_bin}"
+        )
+      }
+      ValidationError::SizeMismatch { found, expected } => {
+        write!(f, "blocks cover {found} bytes, pool holds {expected}")
+      }
+      ValidationError::CounterMismatch { counter, found, expected } => {
+        write!(f, "{counter} is {expected} but the blocks add up to {found}")
+      }
+    }
+  }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Outcome of one [`SpeedAllocator::validate_incremental`] call.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationProgress {
+  /// The budget ran out, call again to continue.
+  InProgress,
+  /// A full pass finished without finding a problem.
+  Complete,
+  Failed(ValidationError),
+}
+
+// Where an interrupted pass resumes: first the physical chain, then every bin's free list, then the index.
+#[derive(Clone, Copy)]
+pub(crate) struct ValidationCursor {
+  generation: u64,
+  phase: Phase,
+  used_blocks: usize,
+  free_blocks: usize,
+  free_bytes: usize,
+  pinned_bytes: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Phase {
+  Chain {
+    next: Option<BlockId>,
+    offset: usize,
+  },
+  // `next` is the entry of free list `idx` to check, `prev` the one checked before it
+  Bins {
+    idx: usize,
+    prev: Option<BlockId>,
+    next: Option<BlockId>,
+    free_blocks: usize,
+    free_bytes: usize,
+  },
+  Index {
+    from: usize,
+  },
+}
+
+impl SpeedAllocator {
+  /// Checks every invariant of the physical chain, the free lists and the counters.
+  pub fn validate(&self) -> Result<(), ValidationError> {
+    let mut cursor = self.start_validation();
+    self.advance_validation(&mut cursor, usize::MAX).map(|_| ())
+  }
+
+  /// Runs the checks of [`validate`](Self::validate) for `budget_blocks` units of work, resuming where the
+  /// previous call stopped: one unit per block of the physical chain, per free list entry, per size class and
+  /// per live allocation, plus one to finish the chain and one to finish the index. There are
+  /// `BIN_COUNT * SUB_BIN` size classes, `usize::BITS - 7` bins of 32 sub-bins each. A pass interrupted by an
+  /// allocation or free starts over, so once the heap stops changing a full pass completes within
+  /// `(blocks + free blocks + live allocations + BIN_COUNT * SUB_BIN + 2) / budget_blocks` calls, rounded up.
+  pub fn validate_incremental(&mut self, budget_blocks: usize) -> ValidationProgress {
+    let mut cursor = match self.validation_cursor.take() {
+      Some(cursor) if cursor.generation == self.generation => cursor,
+      _ => self.start_validation(),
+    };
+    match self.advance_validation(&mut cursor, budget_blocks.max(1)) {
+      Ok(true) => ValidationProgress::Complete,
+      Ok(false) => {
+        self.validation_cursor = Some(cursor);
+        ValidationProgress::InProgress
+      }
+      Err(error) => ValidationProgress::Failed(error),
+    }
+  }
+
+  /// Sums the sizes of every block in the physical chain and compares the total with an externally known
+  /// `expected_total`, failing with [`ValidationError::SizeMismatch`] on a discrepancy.
+  pub fn audit_block_sizes(&self, expected_total: usize) -> Result<(), ValidationError> {
+    let found: usize = self.blocks().map(|block| block.size).sum();
+    if found != expected_total {
+      return Err(ValidationError::SizeMismatch { found, expected: expected_total });
+    }
+    Ok(())
+  }
+
+  fn start_validation(&self) -> ValidationCursor {
+    ValidationCursor {
+      generation: self.generation,
+      phase: Phase::Chain { next: Some(self.head), offset: 0 },
+      used_blocks: 0,
+      free_blocks: 0,
+      free_bytes: 0,
+      pinned_bytes: 0,
+    }
+  }
+
+  // Returns whether the pass is finished.
+  fn advance_validation(&self, cursor: &mut ValidationCursor, mut budget: usize) -> Result<bool, ValidationError> {
+    while budget > 0 {
+      budget -= 1;
+      match cursor.phase {
+        Phase::Chain { next: Some(id), offset } => {
+          self.check_physical_block(id, offset)?;
+          let block = &self.blocks[id];
+          if block.is_free {
+            cursor.free_blocks += 1;
+            cursor.free_bytes += block.size;
+          } else if !block.drained && !block.quarantined {
+            cursor.used_blocks += 1;
+          }
+          if block.pinned {
+            cursor.pinned_bytes += block.size;
+          }
+          cursor.phase = Phase::Chain { next: block.next_physical, offset: offset + block.size };
+        }
+        Phase::Chain { next: None, offset } => {
+          check_equal("pool size", offset, self.pool.len())?;
+          check_equal("live allocations", cursor.used_blocks, self.allocations.len())?;
+          check_equal("free block count", cursor.free_blocks, self.free_block_count)?;
+          check_equal("free bytes", cursor.free_bytes, self.free_bytes)?;
+          check_equal("pinned bytes", cursor.pinned_bytes, self.pinned_bytes)?;
+          let next = self.enter_free_list(0)?;
+          cursor.phase = Phase::Bins { idx: 0, prev: None, next, free_blocks: 0, free_bytes: 0 };
+        }
+        Phase::Bins { idx, prev, next: Some(id), free_blocks, free_bytes } => {
+          // more list entries than free blocks can only come from a cycle
+          let block = self.check_free_list_entry(idx, id, prev, free_blocks >= self.free_block_count)?;
+          cursor.phase = Phase::Bins {
+            idx,
+            prev: Some(id),
+            next: block.next_free,
+            free_blocks: free_blocks + 1,
+            free_bytes: free_bytes + block.size,
+          };
+        }
+        Phase::Bins { idx, next: None, free_blocks, free_bytes, .. } if idx + 1 < BLOCK_COUNT => {
+          let next = self.enter_free_list(idx + 1)?;
+          cursor.phase = Phase::Bins { idx: idx + 1, prev: None, next, free_blocks, free_bytes };
+        }
+        Phase::Bins { next: None, free_blocks, free_bytes, .. } => {
+          check_equal("free blocks in bins", free_blocks, cursor.free_blocks)?;
+          check_equal("free bytes in bins", free_bytes, cursor.free_bytes)?;
+          cursor.phase = Phase::Index { from: 0 };
+        }
+        Phase::Index { from } => {
+          let Some((&offset, &id)) = self.allocations.range(from..).next() else {
+            return Ok(true);
+          };
+          let block = &self.blocks[id];
+          if block.is_free || block.drained || block.quarantined || block.offset + block.adjustment != offset {
+            return Err(ValidationError::StaleIndexEntry { offset });
+          }
+          cursor.phase = Phase::Index { from: offset + 1 };
+        }
+      }
+    }
+    Ok(false)
+  }
+
+  fn check_physical_block(&self, id: BlockId, expected_offset: usize) -> Result<(), ValidationError> {
+    let block = &self.blocks[id];
+    let offset = block.offset;
+    if offset != expected_offset {
+      return Err(ValidationError::Gap { offset, expected_offset });
+    }
+    match block.next_physical {
+      Some(next) if self.blocks[next].prev_physical != Some(id) => {
+        return Err(ValidationError::BrokenPhysicalLink { offset });
+      }
+      Some(next) if block.is_free && self.blocks[next].is_free && !self.blocks[next].fenced => {
+        return Err(ValidationError::UnmergedFreeBlocks { offset });
+      }
+      None if id != self.tail => return Err(ValidationError::BrokenPhysicalLink { offset }),
+      _ => {}
+    }
+    if !block.is_free
+      && !block.drained
+      && !block.quarantined
+      && self.allocations.get(&(offset + block.adjustment)) != Some(&id)
+    {
+      return Err(ValidationError::UnindexedAllocation { offset });
+    }
+    Ok(())
+  }
+
+  // Checks the bitmap bits of size class `idx` against its free list, returning the head of the list.
+  fn enter_free_list(&self, idx: usize) -> Result<Option<BlockId>, ValidationError> {
+    let bin = BinRef::from_index(idx);
+    let head = self.bins[idx];
+    let sub_bins = &self.sub_bin_bitmap[bin.bin()];
+    if sub_bins.test(bin.sub_bin()) != head.is_some()
+      || (bin.sub_bin() == 0 && self.bin_bitmap.test(bin.bin()) == sub_bins.is_empty())
+    {
+      return Err(ValidationError::BitmapMismatch { bin });
+    }
+    Ok(head)
+  }
+
+  // Checks the entry `id` of free list `idx`, reached from `prev`. `overlong` says the lists walked so far
+  // already hold every free block.
+  fn check_free_list_entry(
+    &self,
+    idx: usize,
+    id: BlockId,
+    prev: Option<BlockId>,
+    overlong: bool,
+  ) -> Result<&Block, ValidationError> {
+    let block = &self.blocks[id];
+    if !block.is_free || block.prev_free != prev || overlong {
+      return Err(ValidationError::BrokenFreeList { bin: BinRef::from_index(idx), offset: block.offset });
+    }
+    let expected_bin = binmap_down(block.size);
+    if expected_bin.flat_index() != idx {
+      return Err(ValidationError::MisplacedFreeBlock {
+        offset: block.offset,
+        size: block.size,
+        bin: BinRef::from_index(idx),
+        expected_bin,
+      });
+    }
+    Ok(block)
+  }
+}
+
+fn check_equal(counter: &'static str, found: usize, expected: usize) -> Result<(), ValidationError> {
+  if found == expected {
+    Ok(())
+  } else {
+    Err(ValidationError::CounterMismatch { counter, found, expected })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn audit_compares_block_sizes_with_the_expected_total() {
+    let mut allocator = SpeedAllocator::new(4096);
+    allocator.allocate(100, 8).unwrap();
+    assert_eq!(allocator.audit_block_sizes(4096), Ok(()));
+    assert_eq!(
+      allocator.audit_block_sizes(4000),
+      Err(ValidationError::SizeMismatch { found: 4096, expected: 4000 })
+    );
+  }
+
+  #[test]
+  fn incremental_pass_spends_one_unit_per_free_list_entry() {
+    let mut allocator = SpeedAllocator::new(64 * 1024);
+    let ptrs: Vec<_> = (0..200).map(|_| allocator.allocate(64, 8).unwrap()).collect();
+    for &ptr in ptrs.iter().step_by(2) {
+      allocator.deallocate(ptr);
+    }
+    let stats = allocator.stats();
+    let units = allocator.blocks().count() + stats.free_block_count + stats.live_allocations + BLOCK_COUNT + 2;
+    let mut calls = 1;
+    while allocator.validate_incremental(1) == ValidationProgress::InProgress {
+      calls += 1;
+    }
+    assert_eq!(calls, units);
+    assert_eq!(allocator.validate_incremental(usize::MAX), ValidationProgress::Complete);
+  }
+
+  #[test]
+  fn bitmaps_are_checked_against_the_free_lists() {
+    let mut allocator = SpeedAllocator::new(4096);
+    let ptrs: Vec<_> = [32, 8, 64, 8]
+      .iter()
+      .map(|&size| allocator.allocate(size, 8).unwrap())
+      .collect();
+    allocator.deallocate(ptrs[0]);
+    allocator.deallocate(ptrs[2]);
+    let bin = binmap_down(64);
+    allocator.sub_bin_bitmap[bin.bin()].clear(bin.sub_bin());
+    assert_eq!(allocator.validate(), Err(ValidationError::BitmapMismatch { bin }));
+    allocator.sub_bin_bitmap[bin.bin()].set(bin.sub_bin());
+    assert_eq!(allocator.validate(), Ok(()));
+
+    let empty = binmap_down(128);
+    allocator.sub_bin_bitmap[empty.bin()].set(empty.sub_bin());
+    assert_eq!(
+      allocator.validate_incremental(usize::MAX),
+      ValidationProgress::Failed(ValidationError::BitmapMismatch { bin: empty })
+    );
+  }
+
+  #[test]
+  fn pass_restarts_while_the_heap_changes_and_completes_within_the_bound_after() {
+    let mut allocator = SpeedAllocator::new(64 * 1024);
+    let mut live: Vec<_> = (0..40).map(|i| allocator.allocate(16 + i * 8, 8).unwrap()).collect();
+    let budget = 64;
+    // far more calls than a pass takes, none of which completes since each change restarts the pass
+    for i in 0..200 {
+      assert_eq!(allocator.validate_incremental(budget), ValidationProgress::InProgress);
+      let ptr = live.swap_remove(i * 7 % live.len());
+      allocator.deallocate(ptr);
+      live.push(allocator.allocate(8 + i * 24 % 512, 8).unwrap());
+    }
+
+    let stats = allocator.stats();
+    let units = allocator.blocks().count() + stats.free_block_count + stats.live_allocations + BLOCK_COUNT + 2;
+    let mut calls = 1;
+    while allocator.validate_incremental(budget) == ValidationProgress::InProgress {
+      calls += 1;
+    }
+    assert_eq!(calls, units.div_ceil(budget));
+  }
+}